@@ -0,0 +1,101 @@
+//! Fit checks and line clears, the hot paths of bots searching for placements.
+//!
+//! Each group times the glass against a `cells` baseline doing the same work cell by cell
+//! through the public API, the way the glass did before it kept row and piece masks.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+use core::figures::{Figure, FigureRepr};
+use core::glass::{Cell, Glass};
+
+/// 10x40 glass with a ragged stack in the lower half.
+fn stacked_glass() -> Glass {
+    let mut glass = Glass::new(10, 40);
+    for row in 20 .. 40 {
+        glass[row].fill(Cell::Garbage);
+        glass[row][row * 7 % 10] = Cell::Empty;
+        glass[row][row * 3 % 10] = Cell::Empty;
+    }
+    glass
+}
+
+/// Stacked glass with four more rows to clean.
+fn filled_glass() -> Glass {
+    let mut glass = stacked_glass();
+    for row in [21, 25, 30, 39] {
+        glass[row].fill(Cell::Garbage);
+    }
+    glass
+}
+
+fn fits_cell_by_cell(glass: &Glass, figure: &FigureRepr, (row, col): (isize, isize)) -> bool {
+    figure.blocks.iter().all(|&(x, y)| {
+        let (row, col) = (row + y as isize, col + x as isize);
+        row >= 0 && col >= 0 && glass.cell(row as usize, col as usize).is_ok_and(Cell::is_empty)
+    })
+}
+
+fn landing_cell_by_cell(glass: &Glass) -> Option<(isize, isize)> {
+    glass.figure.as_ref().map(|figure| {
+        let (mut row, col) = figure.position;
+        while fits_cell_by_cell(glass, &figure.figure, (row + 1, col)) {
+            row += 1;
+        }
+        (row, col)
+    })
+}
+
+fn grounded_cell_by_cell(glass: &Glass) -> bool {
+    glass.figure.as_ref().is_some_and(|figure| {
+        let (row, col) = figure.position;
+        !fits_cell_by_cell(glass, &figure.figure, (row + 1, col))
+    })
+}
+
+fn clean_cell_by_cell(glass: &mut Glass) -> Vec<usize> {
+    let filled: Vec<usize> = (0 .. glass.height)
+        .filter(|&row| glass[row].iter().all(|cell| cell.is_filled()))
+        .collect();
+    for &removed in &filled {
+        for row in (1 ..= removed).rev() {
+            for col in 0 .. glass.width {
+                glass[row][col] = glass[row - 1][col];
+            }
+        }
+        glass[0].fill(Cell::Empty);
+    }
+    filled
+}
+
+fn fit_checks(c: &mut Criterion) {
+    let mut glass = stacked_glass();
+    glass.place(FigureRepr::new(Figure::Base), (0, 4));
+
+    let mut group = c.benchmark_group("landing_position");
+    group.bench_function("masks", |b| b.iter(|| black_box(&glass).landing_position()));
+    group.bench_function("cells", |b| b.iter(|| landing_cell_by_cell(black_box(&glass))));
+    group.finish();
+
+    let mut group = c.benchmark_group("is_grounded");
+    group.bench_function("masks", |b| b.iter(|| black_box(&glass).is_grounded()));
+    group.bench_function("cells", |b| b.iter(|| grounded_cell_by_cell(black_box(&glass))));
+    group.finish();
+}
+
+fn line_clears(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clean_four_rows");
+    group.bench_function("masks", |b| b.iter_batched(
+        filled_glass,
+        |mut glass| glass.clean_filled_rows(),
+        BatchSize::SmallInput,
+    ));
+    group.bench_function("cells", |b| b.iter_batched(
+        filled_glass,
+        |mut glass| clean_cell_by_cell(&mut glass),
+        BatchSize::SmallInput,
+    ));
+    group.finish();
+}
+
+criterion_group!(benches, fit_checks, line_clears);
+criterion_main!(benches);
@@ -0,0 +1,33 @@
+//! Compact binary encoding of saved state for save files and the network:
+//! a version byte followed by bincode with variable length integers.
+
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+/// Changes whenever the encoded layout does, older data is rejected.
+pub const VERSION: u8 = 1;
+
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+}
+
+fn error(reason: impl ToString) -> Error {
+    Error::Encoding { reason: reason.to_string() }
+}
+
+pub fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut bytes = vec![VERSION];
+    options().serialize_into(&mut bytes, value).map_err(error)?;
+    Ok(bytes)
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    match bytes.split_first() {
+        Some((&VERSION, encoded)) => options().deserialize(encoded).map_err(error),
+        Some((version, _)) => Err(error(format!("unknown version {}", version))),
+        None => Err(error("no data")),
+    }
+}
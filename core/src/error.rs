@@ -0,0 +1,46 @@
+use std::fmt;
+
+use crate::game::GameState;
+
+/// Everything that can go wrong when driving `core` with untrusted input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// figure without blocks, with too many of them or with a block given twice
+    InvalidShape { blocks: usize },
+    /// piece set without pieces
+    EmptyPieceSet,
+    /// piece set or glass text that doesn't follow the format, `line` counts from 1
+    Parse { line: usize, reason: String },
+    /// piece set file that can't be read
+    Io { reason: String },
+    /// saved state that can't be encoded or decoded
+    Encoding { reason: String },
+    /// fumen that can't be read or a glass fumen can't show
+    Fumen { reason: String },
+    /// glass without cells, wider than a row mask or taller than `MAX_ROWS`
+    InvalidGlassSize { width: usize, height: usize },
+    /// cell outside of the glass
+    OutOfRange { row: usize, col: usize },
+    /// the game can't go from one state to the other
+    IllegalTransition { from: GameState, to: GameState },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidShape { blocks } => write!(f, "invalid figure shape of {} blocks", blocks),
+            Error::EmptyPieceSet => write!(f, "piece set has no pieces"),
+            Error::Parse { line, reason } => write!(f, "line {}: {}", line, reason),
+            Error::Io { reason } => write!(f, "can't read piece set: {}", reason),
+            Error::Encoding { reason } => write!(f, "can't encode or decode the game: {}", reason),
+            Error::Fumen { reason } => write!(f, "invalid fumen: {}", reason),
+            Error::InvalidGlassSize { width, height } => write!(f, "invalid glass size {}x{}", width, height),
+            Error::OutOfRange { row, col } => write!(f, "cell ({}, {}) is outside the glass", row, col),
+            Error::IllegalTransition { from, to } => write!(f, "game can't go from {:?} to {:?}", from, to),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
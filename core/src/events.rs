@@ -0,0 +1,24 @@
+use crate::figures::{Blocks, Kick, RotateDirection};
+use crate::glass::{DropKind, GameOverReason, MoveDirection, TSpin};
+
+/// Something that happened to the game, collected in order until drained.
+/// Only calls that play the game emit events, editing the glass by hand is silent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+    /// `piece` is the index in the piece set of the glass
+    PieceSpawned { piece: usize },
+    Moved { direction: MoveDirection },
+    /// the figure fell `distance` rows, hard drops are followed by the lock
+    Dropped { kind: DropKind, distance: usize },
+    Rotated { direction: RotateDirection, kick: Kick },
+    /// the figure froze into the glass, blocks are in the form of `figure_coordinates`
+    Locked { blocks: Blocks },
+    /// rows as they were before cleaning, top to bottom
+    LinesCleared { rows: Vec<usize> },
+    TSpin(TSpin),
+    LevelUp { level: u32 },
+    /// piece `held` went into the hold box
+    Hold { held: usize },
+    GameOver { reason: GameOverReason },
+}
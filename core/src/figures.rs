@@ -1,5 +1,10 @@
+use std::ops::{Deref, DerefMut};
 
-#[derive(Debug, Clone, Copy)]
+use crate::error::Error;
+use crate::spawn::SpawnRule;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Figure {
     Cube,
     Line,
@@ -10,6 +15,115 @@ pub enum Figure {
     LeftL
 }
 
+impl Figure {
+    pub const ALL: [Figure; 7] = [
+        Figure::Cube,
+        Figure::Line,
+        Figure::Base,
+        Figure::LeftZig,
+        Figure::RightZig,
+        Figure::RightL,
+        Figure::LeftL,
+    ];
+
+    /// Guideline letter naming the figure.
+    pub fn letter(self) -> char {
+        match self {
+            Figure::Cube => 'O',
+            Figure::Line => 'I',
+            Figure::Base => 'T',
+            Figure::LeftZig => 'Z',
+            Figure::RightZig => 'S',
+            Figure::RightL => 'L',
+            Figure::LeftL => 'J',
+        }
+    }
+}
+
+/// Most blocks a figure can be made of.
+pub const MAX_BLOCKS: usize = 16;
+
+/// Block coordinates of a figure, stored inline so figures stay `Copy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "Vec<(i32, i32)>", into = "Vec<(i32, i32)>"))]
+pub struct Blocks {
+    len: usize,
+    /// blocks past `len` are always (0, 0)
+    blocks: [(i32, i32); MAX_BLOCKS],
+}
+
+impl Blocks {
+    /// Fails for no blocks at all, more than `MAX_BLOCKS` or a block given twice.
+    pub fn new(blocks: &[(i32, i32)]) -> Result<Blocks, Error> {
+        let repeated = blocks.iter().enumerate().any(|(i, block)| blocks[.. i].contains(block));
+        if blocks.is_empty() || blocks.len() > MAX_BLOCKS || repeated {
+            return Err(Error::InvalidShape { blocks: blocks.len() });
+        }
+        let mut inline = [(0, 0); MAX_BLOCKS];
+        inline[.. blocks.len()].copy_from_slice(blocks);
+        Ok(Blocks { len: blocks.len(), blocks: inline })
+    }
+}
+
+impl<const N: usize> From<[(i32, i32); N]> for Blocks {
+    fn from(blocks: [(i32, i32); N]) -> Self {
+        Blocks::new(&blocks).expect("figure tables hold valid shapes")
+    }
+}
+
+impl TryFrom<Vec<(i32, i32)>> for Blocks {
+    type Error = Error;
+
+    fn try_from(blocks: Vec<(i32, i32)>) -> Result<Self, Self::Error> {
+        Blocks::new(&blocks)
+    }
+}
+
+impl From<Blocks> for Vec<(i32, i32)> {
+    fn from(blocks: Blocks) -> Self {
+        blocks.to_vec()
+    }
+}
+
+impl<const N: usize> PartialEq<[(i32, i32); N]> for Blocks {
+    fn eq(&self, other: &[(i32, i32); N]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl Deref for Blocks {
+    type Target = [(i32, i32)];
+
+    fn deref(&self) -> &Self::Target {
+        &self.blocks[.. self.len]
+    }
+}
+
+impl DerefMut for Blocks {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.blocks[.. self.len]
+    }
+}
+
+impl<'a> IntoIterator for &'a Blocks {
+    type Item = &'a (i32, i32);
+    type IntoIter = std::slice::Iter<'a, (i32, i32)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Blocks {
+    type Item = &'a mut (i32, i32);
+    type IntoIter = std::slice::IterMut<'a, (i32, i32)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
 impl From<Figure> for FigureRepr {
     fn from(figure: Figure) -> Self {
         use self::Figure::*;
@@ -17,93 +131,96 @@ impl From<Figure> for FigureRepr {
         const X: bool = true;
         let array_repr = match figure {
             Cube => [
-                O, O, O, O,
                 O, X, X, O,
                 O, X, X, O,
                 O, O, O, O,
+                O, O, O, O,
             ],
             Line => [
-                O, O, O, O,
                 O, O, O, O,
                 X, X, X, X,
                 O, O, O, O,
+                O, O, O, O,
             ],
             Base => [
+                O, X, O, O,
+                X, X, X, O,
                 O, O, O, O,
-                O, O, X, O,
-                O, X, X, X,
                 O, O, O, O,
             ],
             LeftZig => [
-                O, O, O, O,
+                X, X, O, O,
                 O, X, X, O,
-                O, O, X, X,
+                O, O, O, O,
                 O, O, O, O,
             ],
             RightZig => [
-                O, O, O, O,
                 O, X, X, O,
                 X, X, O, O,
                 O, O, O, O,
+                O, O, O, O,
             ],
             RightL => [
-                O, X, O, O,
-                O, X, O, O,
-                O, X, X, O,
+                O, O, X, O,
+                X, X, X, O,
+                O, O, O, O,
                 O, O, O, O,
             ],
             LeftL => [
-                O, O, X, O,
-                O, O, X, O,
-                O, X, X, O,
+                X, O, O, O,
+                X, X, X, O,
+                O, O, O, O,
                 O, O, O, O,
             ],
         };
-        array_repr.into()
+        let mut repr = FigureRepr::try_from(array_repr).expect("figure maps have four blocks");
+        // SRS rotates pieces around the centre of their bounding box, not the centre of mass
+        repr.center = match figure {
+            Cube => (1.5, 0.5),
+            Line => (1.5, 1.5),
+            _ => (1.0, 1.0),
+        };
+        repr.kind = Some(figure);
+        repr
     }
 }
 
-impl From<[bool;16]> for FigureRepr {
+impl TryFrom<[bool;16]> for FigureRepr {
+    type Error = Error;
 
-    fn from(figure_map: [bool;16]) -> Self {
-        let mut blocks = [(0, 0); 4];
-        let mut i = 0;
-
-        'main:
-        for row in 0 .. 4 {
-            for col in 0 .. 4 {
-                if figure_map[row * 4 + col] {
-                    blocks[i] = (col as i32, row as i32);
-                    i += 1;
-                    if i == 4 {
-                        break 'main;
-                    }
-                }
-            }
-        }
-
-        if i != 4 {
-            panic!("Not enough points to construct FigureRepr. It need to be exactly 4, but provided: {}", i);
-        }
+    fn try_from(figure_map: [bool;16]) -> Result<Self, Error> {
+        let cells: Vec<(i32, i32)> = (0 .. 16)
+            .filter(|&i| figure_map[i])
+            .map(|i| ((i % 4) as i32, (i / 4) as i32))
+            .collect();
+        let blocks = Blocks::new(&cells)?;
 
+        let count = blocks.len() as f32;
         let center = (
-            blocks.iter().fold(0.0, |sum, &(x, _) | { sum + x as f32 }) / 4.0,
-            blocks.iter().fold(0.0, |sum, &(_, y) | { sum + y as f32 }) / 4.0
+            blocks.iter().fold(0.0, |sum, &(x, _) | { sum + x as f32 }) / count,
+            blocks.iter().fold(0.0, |sum, &(_, y) | { sum + y as f32 }) / count
         );
 
-        FigureRepr {
+        Ok(FigureRepr {
             blocks,
-            center
-        }
+            center,
+            orientation: Orientation::Spawn,
+            kind: None,
+        })
     }
 }
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FigureRepr {
     /// block coordinates
-    pub blocks: [(i32, i32);4],
+    pub blocks: Blocks,
     /// rotation center
     center: (f32, f32),
+    /// current rotation state, starting from `Orientation::Spawn`
+    orientation: Orientation,
+    /// the tetromino this representation was built from, if any
+    kind: Option<Figure>,
 }
 
 impl FigureRepr {
@@ -114,15 +231,60 @@ impl FigureRepr {
         another_repr.into()
     }
 
+    /// Figure drawn in rows of text, any character but `.` and space is a block.
+    /// Rotates around the centre of its bounding box like SRS figures do.
+    pub fn from_rows(rows: &[&str]) -> Result<Self, Error> {
+        let cells: Vec<(i32, i32)> = rows.iter().enumerate()
+            .flat_map(|(y, row)| row.chars().enumerate()
+                .filter(|&(_, c)| c != '.' && c != ' ')
+                .map(move |(x, _)| (x as i32, y as i32)))
+            .collect();
+        let blocks = Blocks::new(&cells)?;
+
+        let width = blocks.iter().map(|&(x, _)| x).max().unwrap_or(0) + 1;
+        let height = blocks.iter().map(|&(_, y)| y).max().unwrap_or(0) + 1;
+        let (mut cx, mut cy) = ((width - 1) as f32 / 2.0, (height - 1) as f32 / 2.0);
+        // blocks only land on whole cells when both coordinates of the centre are whole or both halves
+        if cx.fract() != cy.fract() {
+            if cx.fract() == 0.0 { cx += 0.5 } else { cy += 0.5 }
+        }
+
+        Ok(FigureRepr {
+            blocks,
+            center: (cx, cy),
+            orientation: Orientation::Spawn,
+            kind: None,
+        })
+    }
+
     pub fn rotate(&mut self) {
+        self.rotate_to(RotateDirection::Clockwise);
+    }
+
+    pub fn rotate_to(&mut self, direction: RotateDirection) {
+        use self::RotateDirection::*;
         let (dx, dy) = self.center;
 
         for (x, y) in self.blocks.iter_mut() {
-            let fy = *y as f32;
-            let fx = *x as f32;
-            *x = (-(fy - dy) + dx).ceil() as i32;
-            *y = (fx - dx + dy).ceil() as i32;
+            let fy = *y as f32 - dy;
+            let fx = *x as f32 - dx;
+            let (rx, ry) = match direction {
+                Clockwise => (-fy, fx),
+                CounterClockwise => (fy, -fx),
+                Half => (-fx, -fy),
+            };
+            *x = (rx + dx).ceil() as i32;
+            *y = (ry + dy).ceil() as i32;
         }
+        self.orientation = self.orientation.rotate(direction);
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    pub fn kind(&self) -> Option<Figure> {
+        self.kind
     }
 
     pub fn center_x(&self) -> isize {
@@ -131,7 +293,335 @@ impl FigureRepr {
     }
 
     pub fn min_y(&self) -> isize {
-        self.blocks.iter().map(|(_, y)| { *y }).min().unwrap() as isize
+        self.blocks.iter().map(|(_, y)| { *y }).min().unwrap_or(0) as isize
+    }
+
+    pub fn max_y(&self) -> isize {
+        self.blocks.iter().map(|(_, y)| { *y }).max().unwrap_or(0) as isize
+    }
+
+    pub fn min_x(&self) -> isize {
+        self.blocks.iter().map(|(x, _)| { *x }).min().unwrap_or(0) as isize
+    }
+
+    /// Columns spanned by the blocks.
+    pub fn width(&self) -> usize {
+        let max_x = self.blocks.iter().map(|(x, _)| { *x }).max().unwrap_or(0) as isize;
+        (max_x - self.min_x() + 1) as usize
+    }
+}
+
+/// Rotation states of a figure, named after the SRS convention: 0, R, 2 and L.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Orientation {
+    Spawn,
+    Right,
+    Reverse,
+    Left,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RotateDirection {
+    Clockwise,
+    CounterClockwise,
+    Half,
+}
+
+impl Orientation {
+
+    pub fn rotate(self, direction: RotateDirection) -> Orientation {
+        use self::Orientation::*;
+        let quarters = match direction {
+            RotateDirection::Clockwise => 1,
+            RotateDirection::Half => 2,
+            RotateDirection::CounterClockwise => 3,
+        };
+        let states = [Spawn, Right, Reverse, Left];
+        states[(self as usize + quarters) % 4]
+    }
+}
+
+/// Wall kick that allowed a rotation to succeed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Kick {
+    /// index of the successful test, 0 means the figure rotated in place
+    pub test: usize,
+    /// (x, y) shift applied to the figure, y pointing down as in the glass
+    pub offset: (i32, i32),
+    /// quarter turn shifted by one column and two rows, the kick that makes a mini T-spin a proper one
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tst: bool,
+}
+
+// SRS kick tables as published in the guideline, (x, y) with y pointing UP.
+
+const NO_KICKS: [(i32, i32); 1] = [(0, 0)];
+
+const JLSTZ_KICKS: [[(i32, i32); 5]; 8] = [
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)], // 0 -> R
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],     // R -> 0
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],     // R -> 2
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)], // 2 -> R
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],    // 2 -> L
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],  // L -> 2
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],  // L -> 0
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],    // 0 -> L
+];
+
+const I_KICKS: [[(i32, i32); 5]; 8] = [
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],   // 0 -> R
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],   // R -> 0
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],   // R -> 2
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],   // 2 -> R
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],   // 2 -> L
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],   // L -> 2
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],   // L -> 0
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],   // 0 -> L
+];
+
+/// 180 degree kicks are not part of the guideline, these follow the widely used SRS+ tables.
+const HALF_KICKS: [[(i32, i32); 6]; 4] = [
+    [(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)],     // 0 -> 2
+    [(0, 0), (1, 0), (1, 2), (1, 1), (0, 2), (0, 1)],       // R -> L
+    [(0, 0), (0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0)],  // 2 -> 0
+    [(0, 0), (-1, 0), (-1, 2), (-1, 1), (0, 2), (0, 1)],    // L -> R
+];
+
+/// Rules deciding how figures look in each orientation and which kicks are tried when they rotate.
+pub trait RotationSystem {
+
+    /// Figure representation in its spawn orientation.
+    fn spawn(&self, figure: Figure) -> FigureRepr {
+        FigureRepr::new(figure)
+    }
+
+    /// Spawn conventions of the games using this rotation system.
+    fn spawn_rule(&self) -> SpawnRule {
+        SpawnRule::Guideline
+    }
+
+    /// Figure representation after rotating it in its local frame.
+    fn rotate(&self, figure: &FigureRepr, direction: RotateDirection) -> FigureRepr {
+        let mut rotated = *figure;
+        rotated.rotate_to(direction);
+        rotated
+    }
+
+    /// Shifts to try in order when rotating between two orientations,
+    /// (x, y) with y pointing UP as kick tables are usually published.
+    fn kicks(&self, figure: &FigureRepr, from: Orientation, to: Orientation) -> &'static [(i32, i32)];
+
+    /// Whether kicks may be tried after the in place rotation failed.
+    /// `taken` tells if a cell in the local frame of the rotated figure is occupied.
+    fn can_kick(&self, _rotated: &FigureRepr, _taken: &dyn Fn(i32, i32) -> bool) -> bool {
+        true
+    }
+
+    /// Which rotation system to restore a saved glass with, `None` if it can't be saved.
+    #[cfg(feature = "serde")]
+    fn save(&self) -> Option<SavedRotationSystem> {
+        None
+    }
+}
+
+/// Rotation systems a glass can be saved with.
+#[cfg(feature = "serde")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum SavedRotationSystem {
+    Srs,
+    Ars,
+    Nes,
+}
+
+#[cfg(feature = "serde")]
+impl SavedRotationSystem {
+    pub fn restore(self) -> Box<dyn RotationSystem> {
+        match self {
+            SavedRotationSystem::Srs => Box::new(Srs),
+            SavedRotationSystem::Ars => Box::new(Ars),
+            SavedRotationSystem::Nes => Box::new(Nes),
+        }
+    }
+}
+
+/// Super Rotation System, used by the guideline games.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Srs;
+
+impl RotationSystem for Srs {
+
+    #[cfg(feature = "serde")]
+    fn save(&self) -> Option<SavedRotationSystem> {
+        Some(SavedRotationSystem::Srs)
+    }
+
+    fn kicks(&self, figure: &FigureRepr, from: Orientation, to: Orientation) -> &'static [(i32, i32)] {
+        use self::Orientation::*;
+        let quarter = match (from, to) {
+            (Spawn, Right) => Some(0),
+            (Right, Spawn) => Some(1),
+            (Right, Reverse) => Some(2),
+            (Reverse, Right) => Some(3),
+            (Reverse, Left) => Some(4),
+            (Left, Reverse) => Some(5),
+            (Left, Spawn) => Some(6),
+            (Spawn, Left) => Some(7),
+            _ => None,
+        };
+
+        match (figure.kind(), quarter) {
+            (Some(Figure::Cube), _) => &NO_KICKS,
+            (_, None) if from == to => &NO_KICKS,
+            (_, None) => &HALF_KICKS[from as usize],
+            (Some(Figure::Line), Some(i)) => &I_KICKS[i],
+            (_, Some(i)) => &JLSTZ_KICKS[i],
+        }
+    }
+}
+
+/// Block coordinates of every figure in every orientation, indexed by `Figure` and `Orientation`.
+/// Orientations are keyed by the SRS shapes they look like, whatever the game spawns first.
+type ShapeTable = [[[(i32, i32); 4]; 4]; 7];
+
+fn table_rotate(table: &ShapeTable, figure: &FigureRepr, direction: RotateDirection) -> FigureRepr {
+    let mut rotated = *figure;
+    rotated.rotate_to(direction);
+    if let Some(kind) = figure.kind() {
+        rotated.blocks = table[kind as usize][rotated.orientation() as usize].into();
+    }
+    rotated
+}
+
+fn table_spawn(table: &ShapeTable, figure: Figure) -> FigureRepr {
+    let mut repr = FigureRepr::new(figure);
+    repr.blocks = table[figure as usize][Orientation::Spawn as usize].into();
+    repr
+}
+
+const ARS_SHAPES: ShapeTable = [
+    // Cube
+    [[(1, 1), (2, 1), (1, 2), (2, 2)], [(1, 1), (2, 1), (1, 2), (2, 2)],
+     [(1, 1), (2, 1), (1, 2), (2, 2)], [(1, 1), (2, 1), (1, 2), (2, 2)]],
+    // Line
+    [[(0, 1), (1, 1), (2, 1), (3, 1)], [(2, 0), (2, 1), (2, 2), (2, 3)],
+     [(0, 1), (1, 1), (2, 1), (3, 1)], [(2, 0), (2, 1), (2, 2), (2, 3)]],
+    // Base
+    [[(1, 1), (0, 2), (1, 2), (2, 2)], [(1, 0), (1, 1), (2, 1), (1, 2)],
+     [(0, 1), (1, 1), (2, 1), (1, 2)], [(1, 0), (0, 1), (1, 1), (1, 2)]],
+    // LeftZig
+    [[(0, 1), (1, 1), (1, 2), (2, 2)], [(2, 0), (1, 1), (2, 1), (1, 2)],
+     [(0, 1), (1, 1), (1, 2), (2, 2)], [(2, 0), (1, 1), (2, 1), (1, 2)]],
+    // RightZig
+    [[(1, 1), (2, 1), (0, 2), (1, 2)], [(0, 0), (0, 1), (1, 1), (1, 2)],
+     [(1, 1), (2, 1), (0, 2), (1, 2)], [(0, 0), (0, 1), (1, 1), (1, 2)]],
+    // RightL
+    [[(2, 1), (0, 2), (1, 2), (2, 2)], [(1, 0), (1, 1), (1, 2), (2, 2)],
+     [(0, 1), (1, 1), (2, 1), (0, 2)], [(0, 0), (1, 0), (1, 1), (1, 2)]],
+    // LeftL
+    [[(0, 1), (0, 2), (1, 2), (2, 2)], [(1, 0), (2, 0), (1, 1), (1, 2)],
+     [(0, 1), (1, 1), (2, 1), (2, 2)], [(1, 0), (1, 1), (0, 2), (1, 2)]],
+];
+
+const ARS_KICKS: [(i32, i32); 3] = [(0, 0), (1, 0), (-1, 0)];
+
+/// Arika Rotation System from the TGM series: bottom aligned shapes, kicks one cell right or left.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ars;
+
+impl RotationSystem for Ars {
+
+    #[cfg(feature = "serde")]
+    fn save(&self) -> Option<SavedRotationSystem> {
+        Some(SavedRotationSystem::Ars)
+    }
+
+    fn spawn(&self, figure: Figure) -> FigureRepr {
+        table_spawn(&ARS_SHAPES, figure)
+    }
+
+    fn spawn_rule(&self) -> SpawnRule {
+        SpawnRule::Tgm
+    }
+
+    fn rotate(&self, figure: &FigureRepr, direction: RotateDirection) -> FigureRepr {
+        table_rotate(&ARS_SHAPES, figure, direction)
+    }
+
+    fn kicks(&self, figure: &FigureRepr, _from: Orientation, _to: Orientation) -> &'static [(i32, i32)] {
+        match figure.kind() {
+            Some(Figure::Line) | Some(Figure::Cube) => &NO_KICKS,
+            _ => &ARS_KICKS,
+        }
+    }
+
+    /// L, J and T don't kick when the first blocked cell, in reading order, is in the centre column.
+    fn can_kick(&self, rotated: &FigureRepr, taken: &dyn Fn(i32, i32) -> bool) -> bool {
+        match rotated.kind() {
+            Some(Figure::Base) | Some(Figure::RightL) | Some(Figure::LeftL) => {
+                let mut blocks = rotated.blocks;
+                blocks.sort_by_key(|&(x, y)| (y, x));
+                match blocks.iter().find(|&&(x, y)| taken(x, y)) {
+                    Some(&(x, _)) => x != 1,
+                    None => true,
+                }
+            },
+            _ => true,
+        }
+    }
+}
+
+const NES_SHAPES: ShapeTable = [
+    // Cube
+    [[(1, 1), (2, 1), (1, 2), (2, 2)], [(1, 1), (2, 1), (1, 2), (2, 2)],
+     [(1, 1), (2, 1), (1, 2), (2, 2)], [(1, 1), (2, 1), (1, 2), (2, 2)]],
+    // Line
+    [[(0, 2), (1, 2), (2, 2), (3, 2)], [(2, 0), (2, 1), (2, 2), (2, 3)],
+     [(0, 2), (1, 2), (2, 2), (3, 2)], [(2, 0), (2, 1), (2, 2), (2, 3)]],
+    // Base
+    [[(1, 0), (0, 1), (1, 1), (2, 1)], [(1, 0), (1, 1), (2, 1), (1, 2)],
+     [(0, 1), (1, 1), (2, 1), (1, 2)], [(1, 0), (0, 1), (1, 1), (1, 2)]],
+    // LeftZig
+    [[(0, 1), (1, 1), (1, 2), (2, 2)], [(2, 0), (1, 1), (2, 1), (1, 2)],
+     [(0, 1), (1, 1), (1, 2), (2, 2)], [(2, 0), (1, 1), (2, 1), (1, 2)]],
+    // RightZig
+    [[(1, 1), (2, 1), (0, 2), (1, 2)], [(1, 0), (1, 1), (2, 1), (2, 2)],
+     [(1, 1), (2, 1), (0, 2), (1, 2)], [(1, 0), (1, 1), (2, 1), (2, 2)]],
+    // RightL
+    [[(2, 0), (0, 1), (1, 1), (2, 1)], [(1, 0), (1, 1), (1, 2), (2, 2)],
+     [(0, 1), (1, 1), (2, 1), (0, 2)], [(0, 0), (1, 0), (1, 1), (1, 2)]],
+    // LeftL
+    [[(0, 0), (0, 1), (1, 1), (2, 1)], [(1, 0), (2, 0), (1, 1), (1, 2)],
+     [(0, 1), (1, 1), (2, 1), (2, 2)], [(1, 0), (1, 1), (0, 2), (1, 2)]],
+];
+
+/// Nintendo Rotation System of the classic NES game: right handed shapes and no kicks at all.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Nes;
+
+impl RotationSystem for Nes {
+
+    #[cfg(feature = "serde")]
+    fn save(&self) -> Option<SavedRotationSystem> {
+        Some(SavedRotationSystem::Nes)
+    }
+
+    fn spawn(&self, figure: Figure) -> FigureRepr {
+        table_spawn(&NES_SHAPES, figure)
+    }
+
+    fn spawn_rule(&self) -> SpawnRule {
+        SpawnRule::Nes
+    }
+
+    fn rotate(&self, figure: &FigureRepr, direction: RotateDirection) -> FigureRepr {
+        table_rotate(&NES_SHAPES, figure, direction)
+    }
+
+    fn kicks(&self, _figure: &FigureRepr, _from: Orientation, _to: Orientation) -> &'static [(i32, i32)] {
+        &NO_KICKS
     }
 }
 
@@ -143,10 +633,8 @@ use rand::Rng;
 
 impl Distribution<Figure> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Figure {
-        use self::Figure::*;
-        let values = [Cube, Line, Base, LeftZig, RightZig, LeftL, RightL];
-        let i = rng.gen_range(0..=6);
-        values[i]
+        let i = rng.gen_range(0..Figure::ALL.len());
+        Figure::ALL[i]
     }
 }
 
@@ -185,7 +673,7 @@ mod tests {
 
         /// four consecutive rotations bring figure to its initial shape and position
         fn four_repr_rotations(orig: FigureRepr) -> bool {
-            let mut repr = orig.clone();
+            let mut repr = orig;
 
             repr.rotate();
             repr.rotate();
@@ -197,7 +685,7 @@ mod tests {
 
         /// 1 to 3 consecutive rotations result in distinct figure representation
         fn one_to_three_rotations(orig: FigureRepr, one_to_three: OneToThree) -> bool {
-            let mut repr = orig.clone();
+            let mut repr = orig;
 
             for _ in 0 .. one_to_three.0 {
                 repr.rotate();
@@ -205,6 +693,85 @@ mod tests {
 
             orig.blocks != repr.blocks
         }
+
+        /// rotating back and forth keeps both the shape and the orientation
+        fn rotate_there_and_back(orig: FigureRepr, one_to_three: OneToThree) -> bool {
+            let mut repr = orig;
+            let direction = match one_to_three.0 {
+                1 => RotateDirection::Clockwise,
+                2 => RotateDirection::Half,
+                _ => RotateDirection::CounterClockwise,
+            };
+
+            repr.rotate_to(direction);
+            let turned = repr.orientation();
+            repr.rotate_to(direction);
+            repr.rotate_to(direction);
+            repr.rotate_to(direction);
+
+            turned != orig.orientation() && orig.blocks == repr.blocks && orig.orientation() == repr.orientation()
+        }
+    }
+
+    #[test]
+    fn figure_maps_need_valid_blocks() {
+        let mut map = [false; 16];
+        assert_eq!(FigureRepr::try_from(map).err(), Some(Error::InvalidShape { blocks: 0 }));
+
+        map[.. 3].fill(true);
+        map[4 .. 6].fill(true);
+        assert_eq!(FigureRepr::try_from(map).map(|f| f.blocks.to_vec()), Ok(vec![(0, 0), (1, 0), (2, 0), (0, 1), (1, 1)]));
+
+        assert_eq!(Blocks::new(&[(0, 0), (1, 0), (0, 0)]), Err(Error::InvalidShape { blocks: 3 }));
+        assert_eq!(Blocks::new(&[(0, 0); MAX_BLOCKS + 1]).err(), Some(Error::InvalidShape { blocks: MAX_BLOCKS + 1 }));
+    }
+
+    #[test]
+    fn srs_kicks_for_line_differ_from_other_figures() {
+        use self::Orientation::*;
+        let line = Srs.kicks(&Figure::Line.into(), Spawn, Right);
+        let base = Srs.kicks(&Figure::Base.into(), Spawn, Right);
+        let cube = Srs.kicks(&Figure::Cube.into(), Spawn, Right);
+
+        assert_eq!(line, &[(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)]);
+        assert_eq!(base, &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]);
+        assert_eq!(cube, &[(0, 0)]);
+    }
+
+    quickcheck! {
+
+        /// table driven systems come back to the spawn shape after four rotations
+        fn four_table_rotations(figure: Figure) -> bool {
+            let systems: [&dyn RotationSystem; 2] = [&Ars, &Nes];
+            systems.iter().all(|system| {
+                let spawn = system.spawn(figure);
+                let mut repr = spawn;
+                for _ in 0 .. 4 {
+                    repr = system.rotate(&repr, RotateDirection::Clockwise);
+                }
+                repr.blocks == spawn.blocks && repr.orientation() == Orientation::Spawn
+            })
+        }
+    }
+
+    #[test]
+    fn nes_line_has_two_states() {
+        let spawn = Nes.spawn(Figure::Line);
+        let right = Nes.rotate(&spawn, RotateDirection::Clockwise);
+        let reverse = Nes.rotate(&right, RotateDirection::Clockwise);
+
+        assert_ne!(spawn.blocks, right.blocks);
+        assert_eq!(spawn.blocks, reverse.blocks);
+        assert_eq!(reverse.orientation(), Orientation::Reverse);
+    }
+
+    #[test]
+    fn ars_refuses_kick_blocked_in_center_column() {
+        let base = Ars.rotate(&Ars.spawn(Figure::Base), RotateDirection::Clockwise);
+
+        assert!(!Ars.can_kick(&base, &|x, _| x == 1));
+        assert!(Ars.can_kick(&base, &|x, _| x == 0));
+        assert!(Ars.can_kick(&Ars.spawn(Figure::RightZig), &|x, _| x == 1));
     }
 
 }
\ No newline at end of file
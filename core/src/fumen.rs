@@ -0,0 +1,769 @@
+//! Fumen v115 strings, the format setups and puzzles are shared in by the fumen editor
+//! and the tetris-fumen tools.
+//!
+//! A fumen field is 10 cells wide and 23 rows tall with a garbage row under it,
+//! pages are stored as differences from the field the previous page left behind.
+
+use crate::error::{Error, Result};
+use crate::figures::{Figure, FigureRepr, Orientation, RotateDirection};
+use crate::glass::{Cell, FigureInGlass, Glass};
+
+pub const WIDTH: usize = 10;
+/// rows of the field, the garbage row not counted
+const TOP: usize = 23;
+/// visible rows of the decoded glasses, the rows above them are buffer
+const VISIBLE: usize = 20;
+/// cells of the field including the garbage row
+const BLOCKS: usize = (TOP + 1) * WIDTH;
+
+const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_TABLE: &[u8] = b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+/// comment characters are packed four into five table characters, in base 96
+const COMMENT_BASE: usize = 96;
+const PREFIX: &str = "v115@";
+
+/// What happens between a page and the next one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Flags {
+    /// the piece locks and full rows clear
+    pub lock: bool,
+    /// the garbage row is pushed into the field
+    pub rise: bool,
+    /// the field is flipped left to right
+    pub mirror: bool,
+    /// the editor shows pieces in colour rather than gray
+    pub colorize: bool,
+}
+
+impl Default for Flags {
+    fn default() -> Self {
+        Flags { lock: true, rise: false, mirror: false, colorize: true }
+    }
+}
+
+/// One page of a fumen.
+pub struct Page {
+    /// field of the page, its figure is the piece of the page
+    pub glass: Glass,
+    /// row under the glass pushed up by `rise`
+    pub garbage: [Cell; WIDTH],
+    pub comment: String,
+    pub flags: Flags,
+}
+
+impl Page {
+    pub fn new(glass: Glass) -> Page {
+        Page { glass, garbage: [Cell::Empty; WIDTH], comment: String::new(), flags: Flags::default() }
+    }
+
+    pub fn with_comment(mut self, comment: &str) -> Page {
+        self.comment = comment.to_string();
+        self
+    }
+
+    pub fn with_flags(mut self, flags: Flags) -> Page {
+        self.flags = flags;
+        self
+    }
+}
+
+/// Reads the pages of a fumen, anything before `v115@` like the address of the editor is skipped.
+/// Decoded glasses are 10 by 20 with 3 buffer rows.
+pub fn decode(fumen: &str) -> Result<Vec<Page>> {
+    let (_, data) = fumen.split_once(PREFIX).ok_or_else(|| error("only v115 fumen is supported"))?;
+    let mut values = Values::new(data)?;
+
+    let mut pages = Vec::new();
+    let mut previous = Field::empty();
+    let mut repeat = 0;
+    let mut comment = String::new();
+    while !values.is_empty() {
+        let field = if repeat > 0 {
+            repeat -= 1;
+            previous.clone()
+        } else {
+            let (field, changed) = decode_field(&mut values, &previous)?;
+            if !changed {
+                repeat = values.poll(1)?;
+            }
+            field
+        };
+
+        let (piece, flags, comment_changed) = decode_action(values.poll(3)?)?;
+        if comment_changed {
+            let length = values.poll(2)?;
+            let mut escaped = String::with_capacity(length + 3);
+            for _ in 0 .. length.div_ceil(4) {
+                let mut value = values.poll(5)?;
+                for _ in 0 .. 4 {
+                    let c = COMMENT_TABLE.get(value % COMMENT_BASE).ok_or_else(|| error("invalid comment"))?;
+                    escaped.push(*c as char);
+                    value /= COMMENT_BASE;
+                }
+            }
+            escaped.truncate(length);
+            comment = unescape(&escaped);
+        }
+
+        pages.push(Page {
+            glass: field.to_glass(piece)?,
+            garbage: field.garbage(),
+            comment: comment.clone(),
+            flags,
+        });
+        previous = field.after(piece, flags);
+    }
+    Ok(pages)
+}
+
+/// Writes the pages as a fumen. Glasses have to be 10 cells wide and pieces tetrominoes.
+pub fn encode(pages: &[Page]) -> Result<String> {
+    let frames = pages.iter()
+        .map(|page| Frame::new(&page.glass, &page.garbage, &page.comment, page.flags))
+        .collect::<Result<Vec<_>>>()?;
+    encode_frames(&frames)
+}
+
+/// Fumen of a single page showing `glass`.
+pub fn encode_glass(glass: &Glass) -> Result<String> {
+    encode_frames(&[Frame::new(glass, &[Cell::Empty; WIDTH], "", Flags::default())?])
+}
+
+fn error(reason: impl ToString) -> Error {
+    Error::Fumen { reason: reason.to_string() }
+}
+
+/// Piece numbers fumen uses for cells, 0 is empty and 8 gray.
+fn figure_number(figure: Figure) -> usize {
+    match figure {
+        Figure::Line => 1,
+        Figure::RightL => 2,
+        Figure::Cube => 3,
+        Figure::LeftZig => 4,
+        Figure::Base => 5,
+        Figure::LeftL => 6,
+        Figure::RightZig => 7,
+    }
+}
+
+fn number_figure(number: usize) -> Option<Figure> {
+    Figure::ALL.iter().copied().find(|&figure| figure_number(figure) == number)
+}
+
+fn cell_number(cell: Cell) -> u8 {
+    match cell {
+        Cell::Empty => 0,
+        Cell::Piece(figure) => figure_number(figure) as u8,
+        Cell::Garbage | Cell::Custom(_) => 8,
+    }
+}
+
+fn number_cell(number: u8) -> Cell {
+    match number_figure(number as usize) {
+        Some(figure) => Cell::Piece(figure),
+        None if number == 0 => Cell::Empty,
+        None => Cell::Garbage,
+    }
+}
+
+/// Rotation numbers of the orientations.
+fn orientation_number(orientation: Orientation) -> usize {
+    match orientation {
+        Orientation::Reverse => 0,
+        Orientation::Right => 1,
+        Orientation::Spawn => 2,
+        Orientation::Left => 3,
+    }
+}
+
+/// Piece as fumen places it: around its SRS rotation centre, y pointing up from the bottom row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Piece {
+    figure: Figure,
+    orientation: Orientation,
+    x: i32,
+    y: i32,
+}
+
+impl Piece {
+    fn blocks(&self) -> [(i32, i32); 4] {
+        let spawn = match self.figure {
+            Figure::Line => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+            Figure::Base => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+            Figure::Cube => [(0, 0), (1, 0), (0, 1), (1, 1)],
+            Figure::RightL => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+            Figure::LeftL => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+            Figure::RightZig => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+            Figure::LeftZig => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+        };
+        spawn.map(|(x, y)| {
+            let (x, y) = match self.orientation {
+                Orientation::Spawn => (x, y),
+                Orientation::Right => (y, -x),
+                Orientation::Reverse => (-x, -y),
+                Orientation::Left => (-y, x),
+            };
+            (self.x + x, self.y + y)
+        })
+    }
+
+    /// Shift from the cell fumen stores O, I, S and Z at to their centre, older versions placed them so.
+    fn stored_shift(&self) -> (i32, i32) {
+        use self::Orientation::*;
+        match (self.figure, self.orientation) {
+            (Figure::Cube, Left) => (1, -1),
+            (Figure::Cube, Reverse) => (1, 0),
+            (Figure::Cube, Spawn) => (0, -1),
+            (Figure::Line, Reverse) => (1, 0),
+            (Figure::Line, Left) => (0, -1),
+            (Figure::RightZig, Spawn) => (0, -1),
+            (Figure::RightZig, Right) => (-1, 0),
+            (Figure::LeftZig, Spawn) => (0, -1),
+            (Figure::LeftZig, Left) => (1, 0),
+            _ => (0, 0),
+        }
+    }
+
+    /// Piece matching the figure of the glass, rows counted from the bottom of `total` rows.
+    fn of_figure(figure: &FigureInGlass, total: usize) -> Result<Piece> {
+        let kind = figure.figure.kind().ok_or_else(|| error("only tetrominoes can be written"))?;
+        let (row, col) = figure.position;
+        let mut cells: Vec<(i32, i32)> = figure.figure.blocks.iter()
+            .map(|&(x, y)| (x + col as i32, total as i32 - 1 - (y + row as i32)))
+            .collect();
+
+        let mut piece = Piece { figure: kind, orientation: figure.figure.orientation(), x: 0, y: 0 };
+        let mut blocks = piece.blocks().to_vec();
+        let min = |blocks: &[(i32, i32)]| {
+            let x = blocks.iter().map(|&(x, _)| x).min().unwrap_or(0);
+            let y = blocks.iter().map(|&(_, y)| y).min().unwrap_or(0);
+            (x, y)
+        };
+        let ((cells_x, cells_y), (blocks_x, blocks_y)) = (min(&cells), min(&blocks));
+        piece.x = cells_x - blocks_x;
+        piece.y = cells_y - blocks_y;
+
+        blocks = piece.blocks().to_vec();
+        blocks.sort();
+        cells.sort();
+        if blocks != cells {
+            return Err(error("figure doesn't look like a tetromino in its orientation"));
+        }
+        Ok(piece)
+    }
+
+    /// Figure in a glass of `total` rows showing the piece.
+    fn to_figure(self, total: usize) -> FigureInGlass {
+        let mut figure = FigureRepr::new(self.figure);
+        let direction = match self.orientation {
+            Orientation::Spawn => None,
+            Orientation::Right => Some(RotateDirection::Clockwise),
+            Orientation::Reverse => Some(RotateDirection::Half),
+            Orientation::Left => Some(RotateDirection::CounterClockwise),
+        };
+        if let Some(direction) = direction {
+            figure.rotate_to(direction);
+        }
+        let cells = self.blocks().map(|(x, y)| (x, total as i32 - 1 - y));
+        let row = cells.iter().map(|&(_, row)| row).min().unwrap_or(0) - figure.min_y() as i32;
+        let col = cells.iter().map(|&(col, _)| col).min().unwrap_or(0) - figure.min_x() as i32;
+        FigureInGlass { figure, position: (row as isize, col as isize), piece: None }
+    }
+}
+
+/// Cells as fumen numbers, the top row first and the garbage row last.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Field {
+    cells: [u8; BLOCKS],
+}
+
+impl Field {
+    fn empty() -> Field {
+        Field { cells: [0; BLOCKS] }
+    }
+
+    /// Index of a cell, `y` counts rows up from the bottom one, -1 is the garbage row.
+    fn index(x: i32, y: i32) -> Option<usize> {
+        if (0 .. WIDTH as i32).contains(&x) && (-1 .. TOP as i32).contains(&y) {
+            Some((TOP as i32 - 1 - y) as usize * WIDTH + x as usize)
+        } else {
+            None
+        }
+    }
+
+    fn of_glass(glass: &Glass, garbage: &[Cell; WIDTH]) -> Result<Field> {
+        if glass.width != WIDTH {
+            return Err(error(format!("glass is {} cells wide rather than {}", glass.width, WIDTH)));
+        }
+        let total = glass.buffer() + glass.height;
+        let mut field = Field::empty();
+        for row in 0 .. total {
+            let y = (total - 1 - row) as i32;
+            for (x, &cell) in glass[row].iter().enumerate() {
+                match Field::index(x as i32, y) {
+                    Some(index) => field.cells[index] = cell_number(cell),
+                    None if cell.is_empty() => {},
+                    None => return Err(error(format!("cells above row {} don't fit the field", TOP))),
+                }
+            }
+        }
+        for (x, &cell) in garbage.iter().enumerate() {
+            field.cells[TOP * WIDTH + x] = cell_number(cell);
+        }
+        Ok(field)
+    }
+
+    fn to_glass(&self, piece: Option<Piece>) -> Result<Glass> {
+        let mut glass = Glass::try_new(WIDTH, VISIBLE)?.with_buffer(TOP - VISIBLE);
+        for row in 0 .. TOP {
+            for x in 0 .. WIDTH {
+                glass[row][x] = number_cell(self.cells[row * WIDTH + x]);
+            }
+        }
+        if let Some(piece) = piece {
+            let figure = piece.to_figure(TOP);
+            if !glass.place(figure.figure, figure.position) {
+                return Err(error(format!("{:?} piece doesn't fit the field", piece.figure)));
+            }
+            let index = glass.piece_set().find(piece.figure);
+            if let Some(figure) = &mut glass.figure {
+                figure.piece = index;
+            }
+        }
+        Ok(glass)
+    }
+
+    fn garbage(&self) -> [Cell; WIDTH] {
+        let mut garbage = [Cell::Empty; WIDTH];
+        for (x, cell) in garbage.iter_mut().enumerate() {
+            *cell = number_cell(self.cells[TOP * WIDTH + x]);
+        }
+        garbage
+    }
+
+    /// Field the next page starts from.
+    fn after(&self, piece: Option<Piece>, flags: Flags) -> Field {
+        let mut field = self.clone();
+        if !flags.lock {
+            return field;
+        }
+        if let Some(piece) = piece {
+            for (x, y) in piece.blocks() {
+                if let Some(index) = Field::index(x, y) {
+                    field.cells[index] = figure_number(piece.figure) as u8;
+                }
+            }
+        }
+
+        let (play, garbage) = field.cells.split_at_mut(TOP * WIDTH);
+        let kept: Vec<[u8; WIDTH]> = play.chunks(WIDTH)
+            .filter(|row| row.contains(&0))
+            .map(|row| row.try_into().expect("rows are WIDTH cells"))
+            .collect();
+        play.fill(0);
+        for (row, cells) in play.rchunks_mut(WIDTH).zip(kept.iter().rev()) {
+            row.copy_from_slice(cells);
+        }
+
+        if flags.rise {
+            play.copy_within(WIDTH .., 0);
+            play[(TOP - 1) * WIDTH ..].copy_from_slice(garbage);
+            garbage.fill(0);
+        }
+        if flags.mirror {
+            play.chunks_mut(WIDTH).for_each(|row| row.reverse());
+        }
+        field
+    }
+}
+
+/// Page ready to be written.
+struct Frame<'a> {
+    field: Field,
+    piece: Option<Piece>,
+    comment: &'a str,
+    flags: Flags,
+}
+
+impl<'a> Frame<'a> {
+    fn new(glass: &Glass, garbage: &[Cell; WIDTH], comment: &'a str, flags: Flags) -> Result<Frame<'a>> {
+        let total = glass.buffer() + glass.height;
+        let piece = glass.figure.as_ref().map(|figure| Piece::of_figure(figure, total)).transpose()?;
+        Ok(Frame { field: Field::of_glass(glass, garbage)?, piece, comment, flags })
+    }
+}
+
+/// Table characters of a fumen as numbers, multi character values are little endian.
+struct Values {
+    values: Vec<usize>,
+    next: usize,
+}
+
+impl Values {
+    /// Skips the `?` the editor puts into long fumens.
+    fn new(data: &str) -> Result<Values> {
+        let values = data.bytes()
+            .filter(|&c| c != b'?')
+            .map(|c| TABLE.iter().position(|&t| t == c).ok_or_else(|| error(format!("invalid character {:?}", c as char))))
+            .collect::<Result<_>>()?;
+        Ok(Values { values, next: 0 })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.next >= self.values.len()
+    }
+
+    fn poll(&mut self, count: usize) -> Result<usize> {
+        let values = self.values.get(self.next .. self.next + count).ok_or_else(|| error("data ends too early"))?;
+        self.next += count;
+        Ok(values.iter().rev().fold(0, |value, &v| value * TABLE.len() + v))
+    }
+
+    fn push(values: &mut Vec<usize>, mut value: usize, count: usize) {
+        for _ in 0 .. count {
+            values.push(value % TABLE.len());
+            value /= TABLE.len();
+        }
+    }
+}
+
+/// Field of the page and whether it differs from `previous`.
+fn decode_field(values: &mut Values, previous: &Field) -> Result<(Field, bool)> {
+    let mut field = Field::empty();
+    let mut changed = true;
+    let mut index = 0;
+    while index < BLOCKS {
+        let value = values.poll(2)?;
+        let (diff, count) = (value / BLOCKS, value % BLOCKS + 1);
+        if diff == 8 && count == BLOCKS {
+            changed = false;
+        }
+        if index + count > BLOCKS {
+            return Err(error("field has too many cells"));
+        }
+        for i in index .. index + count {
+            let number = (previous.cells[i] as usize + diff).checked_sub(8).filter(|&n| n <= 8);
+            field.cells[i] = number.ok_or_else(|| error("invalid cell"))? as u8;
+        }
+        index += count;
+    }
+    Ok((field, changed))
+}
+
+/// Runs of equal differences from `previous`, each stored in two characters.
+fn encode_field(previous: &Field, field: &Field) -> Vec<usize> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for (&before, &after) in previous.cells.iter().zip(field.cells.iter()) {
+        let diff = after as usize + 8 - before as usize;
+        match runs.last_mut() {
+            Some((last, count)) if *last == diff => *count += 1,
+            _ => runs.push((diff, 1)),
+        }
+    }
+    runs.into_iter().map(|(diff, count)| diff * BLOCKS + count - 1).collect()
+}
+
+/// Piece, flags and whether the comment changes.
+fn decode_action(mut value: usize) -> Result<(Option<Piece>, Flags, bool)> {
+    let mut take = |base: usize| {
+        let taken = value % base;
+        value /= base;
+        taken
+    };
+    let number = take(8);
+    let orientation = [Orientation::Reverse, Orientation::Right, Orientation::Spawn, Orientation::Left][take(4)];
+    let position = take(BLOCKS);
+    let rise = take(2) == 1;
+    let mirror = take(2) == 1;
+    let colorize = take(2) == 1;
+    let comment = take(2) == 1;
+    let lock = take(2) == 0;
+
+    let piece = match number {
+        0 | 8 => None,
+        number => {
+            let figure = number_figure(number).ok_or_else(|| error("invalid piece"))?;
+            let mut piece = Piece { figure, orientation, x: (position % WIDTH) as i32, y: TOP as i32 - 1 - (position / WIDTH) as i32 };
+            let (dx, dy) = piece.stored_shift();
+            piece.x += dx;
+            piece.y += dy;
+            Some(piece)
+        },
+    };
+    Ok((piece, Flags { lock, rise, mirror, colorize }, comment))
+}
+
+fn encode_action(piece: Option<Piece>, flags: Flags, comment: bool) -> Result<usize> {
+    let (number, orientation, position) = match piece {
+        None => (0, 0, 0),
+        Some(piece) => {
+            let (dx, dy) = piece.stored_shift();
+            let position = Field::index(piece.x - dx, piece.y - dy).ok_or_else(|| error("piece is outside the field"))?;
+            (figure_number(piece.figure), orientation_number(piece.orientation), position)
+        },
+    };
+    let bits = [!flags.lock, comment, flags.colorize, flags.mirror, flags.rise];
+    let value = bits.iter().fold(0, |value, &bit| value * 2 + bit as usize);
+    Ok(((value * BLOCKS + position) * 4 + orientation) * 8 + number)
+}
+
+fn encode_frames(frames: &[Frame]) -> Result<String> {
+    let mut values = Vec::new();
+    let mut previous = Field::empty();
+    // where the count of pages repeating the field is, while they do
+    let mut repeat: Option<usize> = None;
+    let mut comment = "";
+    for (i, frame) in frames.iter().enumerate() {
+        let runs = encode_field(&previous, &frame.field);
+        let changed = runs != [8 * BLOCKS + BLOCKS - 1];
+        match repeat {
+            Some(at) if !changed && values[at] < TABLE.len() - 1 => values[at] += 1,
+            _ => {
+                runs.iter().for_each(|&run| Values::push(&mut values, run, 2));
+                repeat = if changed { None } else {
+                    values.push(0);
+                    Some(values.len() - 1)
+                };
+            },
+        }
+
+        let comment_changed = if i == 0 { !frame.comment.is_empty() } else { frame.comment != comment };
+        Values::push(&mut values, encode_action(frame.piece, frame.flags, comment_changed)?, 3);
+        if comment_changed {
+            let escaped = escape(frame.comment);
+            if escaped.len() >= TABLE.len() * TABLE.len() {
+                return Err(error("comment is too long"));
+            }
+            Values::push(&mut values, escaped.len(), 2);
+            for chunk in escaped.as_bytes().chunks(4) {
+                let value = chunk.iter().rev().fold(0, |value, c| {
+                    let index = COMMENT_TABLE.iter().position(|t| t == c).unwrap_or(0);
+                    value * COMMENT_BASE + index
+                });
+                Values::push(&mut values, value, 5);
+            }
+            comment = frame.comment;
+        }
+        previous = frame.field.after(frame.piece, frame.flags);
+    }
+
+    let data: String = values.iter().map(|&value| TABLE[value] as char).collect();
+    Ok(format!("{}{}", PREFIX, split_lines(&data)))
+}
+
+/// Puts a `?` after the first 42 characters and then after every 47, like the editor does.
+fn split_lines(data: &str) -> String {
+    if data.len() <= 42 {
+        return data.to_string();
+    }
+    let (head, tail) = data.split_at(42);
+    let mut lines = vec![head];
+    lines.extend(tail.as_bytes().chunks(47).map(|chunk| std::str::from_utf8(chunk).expect("data is ascii")));
+    lines.join("?")
+}
+
+/// JavaScript `escape`, comments are stored escaped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for unit in text.encode_utf16() {
+        match char::from_u32(unit as u32) {
+            Some(c) if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) => escaped.push(c),
+            _ if unit < 0x100 => escaped.push_str(&format!("%{:02X}", unit)),
+            _ => escaped.push_str(&format!("%u{:04X}", unit)),
+        }
+    }
+    escaped
+}
+
+/// JavaScript `unescape`, malformed sequences are kept as they are.
+fn unescape(escaped: &str) -> String {
+    let bytes = escaped.as_bytes();
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = |from: usize, len: usize| {
+            escaped.get(from .. from + len).and_then(|hex| u16::from_str_radix(hex, 16).ok())
+        };
+        let decoded = match bytes[i] {
+            b'%' if bytes.get(i + 1) == Some(&b'u') => hex(i + 2, 4).map(|unit| (unit, 6)),
+            b'%' => hex(i + 1, 2).map(|unit| (unit, 3)),
+            _ => None,
+        };
+        let (unit, len) = decoded.unwrap_or((bytes[i] as u16, 1));
+        units.push(unit);
+        i += len;
+    }
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::glass::MoveDirection;
+
+    fn move_figure(glass: &mut Glass, direction: MoveDirection, times: usize) {
+        for _ in 0 .. times {
+            glass.relocate_figure(direction);
+        }
+    }
+
+    #[test]
+    fn empty_page() {
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].glass.figure.is_none());
+        assert_eq!((pages[0].comment.as_str(), pages[0].flags), ("", Flags::default()));
+
+        assert_eq!(encode(&pages).unwrap(), "v115@vhAAgH");
+    }
+
+    #[test]
+    fn gray_field_round_trips() {
+        let fumen = "v115@9gF8DeF8DeF8DeF8NeAgH";
+        let pages = decode(fumen).unwrap();
+        let glass = &pages[0].glass;
+        for row in 19 ..= 22 {
+            assert_eq!(glass[row][.. 6], [Cell::Garbage; 6]);
+            assert_eq!(glass[row][6 ..], [Cell::Empty; 4]);
+        }
+        assert!(glass[18].iter().all(|cell| cell.is_empty()));
+
+        assert_eq!(encode_glass(glass).unwrap(), fumen);
+    }
+
+    #[test]
+    fn pages_round_trip() {
+        let mut first = Glass::new(10, 20).with_buffer(3);
+        first[22][.. 9].fill(Cell::Garbage);
+        first[21][0] = Cell::Piece(Figure::LeftZig);
+        first.spawn(Figure::Line);
+        first.rotate_figure(RotateDirection::Clockwise);
+        move_figure(&mut first, MoveDirection::Right, 5);
+        move_figure(&mut first, MoveDirection::Down, 20);
+        let placed = first.figure_coordinates();
+
+        let mut second = Glass::new(10, 20).with_buffer(3);
+        second.spawn(Figure::Base);
+        second.rotate_figure(RotateDirection::CounterClockwise);
+        let pages = vec![
+            Page::new(first).with_comment("I vertical, ダブル 100%"),
+            Page::new(Glass::new(10, 20).with_buffer(3)).with_comment("I vertical, ダブル 100%"),
+            Page::new(second).with_flags(Flags { lock: false, rise: true, mirror: true, colorize: false }),
+        ];
+
+        let fumen = encode(&pages).unwrap();
+        let decoded = decode(&fumen).unwrap();
+        assert_eq!(encode(&decoded).unwrap(), fumen);
+
+        assert_eq!(decoded[0].glass.figure_coordinates(), placed);
+        assert_eq!(decoded[0].glass[22][.. 9], [Cell::Garbage; 9]);
+        assert_eq!(decoded[0].comment, "I vertical, ダブル 100%");
+        assert_eq!(decoded[1].comment, decoded[0].comment);
+        assert_eq!(decoded[2].comment, "");
+        assert_eq!(decoded[2].glass.figure.map(|f| f.figure.orientation()), Some(Orientation::Left));
+        assert_eq!(decoded[2].flags, pages[2].flags);
+        for page in &decoded {
+            assert_eq!(page.glass.figure.and_then(|f| f.piece), page.glass.figure.and_then(|f| f.figure.kind()).map(|f| f as usize));
+        }
+    }
+
+    #[test]
+    fn o_i_s_and_z_are_read_where_the_editor_stores_them() {
+        use self::Orientation::*;
+        // pieces resting on the floor as tetris-fumen encodes them, the stored cell only depends on the cells taken
+        let cases = [
+            ("v115@vhAzLJ", Figure::Cube, Spawn, [(5, 21), (5, 22), (6, 21), (6, 22)]),
+            ("v115@vhArLJ", Figure::Cube, Right, [(5, 21), (5, 22), (6, 21), (6, 22)]),
+            ("v115@vhAjLJ", Figure::Cube, Reverse, [(5, 21), (5, 22), (6, 21), (6, 22)]),
+            ("v115@vhA7LJ", Figure::Cube, Left, [(5, 21), (5, 22), (6, 21), (6, 22)]),
+            ("v115@vhARQJ", Figure::Line, Spawn, [(3, 22), (4, 22), (5, 22), (6, 22)]),
+            ("v115@vhApFJ", Figure::Line, Right, [(3, 19), (3, 20), (3, 21), (3, 22)]),
+            ("v115@vhABQJ", Figure::Line, Reverse, [(3, 22), (4, 22), (5, 22), (6, 22)]),
+            ("v115@vhA5FJ", Figure::Line, Left, [(3, 19), (3, 20), (3, 21), (3, 22)]),
+            ("v115@vhAXMJ", Figure::RightZig, Spawn, [(5, 22), (6, 21), (6, 22), (7, 21)]),
+            ("v115@vhAPMJ", Figure::RightZig, Right, [(5, 20), (5, 21), (6, 21), (6, 22)]),
+            ("v115@vhAHMJ", Figure::RightZig, Reverse, [(5, 22), (6, 21), (6, 22), (7, 21)]),
+            ("v115@vhAfMJ", Figure::RightZig, Left, [(5, 20), (5, 21), (6, 21), (6, 22)]),
+            ("v115@vhA0KJ", Figure::LeftZig, Spawn, [(2, 21), (3, 21), (3, 22), (4, 22)]),
+            ("v115@vhAMKJ", Figure::LeftZig, Right, [(2, 21), (2, 22), (3, 20), (3, 21)]),
+            ("v115@vhAkKJ", Figure::LeftZig, Reverse, [(2, 21), (3, 21), (3, 22), (4, 22)]),
+            ("v115@vhAcKJ", Figure::LeftZig, Left, [(2, 21), (2, 22), (3, 20), (3, 21)]),
+        ];
+        for (fumen, figure, orientation, cells) in cases {
+            let pages = decode(fumen).unwrap();
+            let piece = pages[0].glass.figure.unwrap();
+            assert_eq!((piece.figure.kind(), piece.figure.orientation()), (Some(figure), orientation), "{}", fumen);
+            let mut blocks: Vec<_> = pages[0].glass.figure_coordinates().unwrap().into();
+            blocks.sort_unstable();
+            assert_eq!(blocks, cells, "{}", fumen);
+
+            assert_eq!(encode(&pages).unwrap(), fumen);
+        }
+    }
+
+    #[test]
+    fn o_against_the_right_wall_is_written_in_every_orientation() {
+        for direction in [RotateDirection::Clockwise, RotateDirection::Half, RotateDirection::CounterClockwise] {
+            let mut glass = Glass::new(10, 20).with_buffer(3);
+            glass.spawn(Figure::Cube);
+            move_figure(&mut glass, MoveDirection::Right, 10);
+            glass.rotate_figure(direction);
+            move_figure(&mut glass, MoveDirection::Down, 25);
+
+            let decoded = decode(&encode_glass(&glass).unwrap()).unwrap();
+            assert_eq!(decoded[0].glass.figure_coordinates(), glass.figure_coordinates());
+        }
+    }
+
+    #[test]
+    fn locked_pieces_clear_rows_for_the_next_page() {
+        let mut glass = Glass::new(10, 20).with_buffer(3);
+        glass[22][.. 6].fill(Cell::Garbage);
+        glass[22][8 ..].fill(Cell::Garbage);
+        glass.spawn(Figure::Cube);
+        move_figure(&mut glass, MoveDirection::Right, 2);
+        move_figure(&mut glass, MoveDirection::Down, 20);
+
+        let mut fumen = encode_glass(&glass).unwrap();
+        // a second page with the field the first one left behind
+        fumen.push_str("vhAAgH");
+        let pages = decode(&fumen).unwrap();
+        assert_eq!(pages[1].glass[22][.. 6], [Cell::Empty; 6]);
+        assert_eq!(pages[1].glass[22][6 ..= 7], [Cell::Piece(Figure::Cube); 2]);
+    }
+
+    #[test]
+    fn invalid_fumens_are_errors() {
+        let is_error = |fumen: &str| matches!(decode(fumen), Err(Error::Fumen { .. }));
+        assert!(is_error("v110@vhAAgH"));
+        assert!(is_error("v115@vhAAg"));
+        assert!(is_error("v115@vh!AgH"));
+        assert!(decode("https://fumen.zui.jp/?v115@vhAAgH").is_ok());
+
+        assert!(matches!(encode_glass(&Glass::new(12, 20)), Err(Error::Fumen { .. })));
+        let mut tall = Glass::new(10, 30);
+        tall[0][0] = Cell::Garbage;
+        assert!(matches!(encode_glass(&tall), Err(Error::Fumen { .. })));
+    }
+
+    #[test]
+    fn pieces_overlapping_the_field_are_errors() {
+        let mut glass = Glass::new(10, 20).with_buffer(3);
+        glass.spawn(Figure::Cube);
+        move_figure(&mut glass, MoveDirection::Down, 20);
+        let fumen = encode_glass(&glass).unwrap();
+        assert!(decode(&fumen).unwrap()[0].glass.figure.is_some());
+
+        let (col, row) = glass.figure_coordinates().unwrap()[0];
+        glass[row as usize][col as usize] = Cell::Garbage;
+        let fumen = encode_glass(&glass).unwrap();
+        assert!(matches!(decode(&fumen), Err(Error::Fumen { .. })));
+    }
+
+    #[test]
+    fn comments_escape_like_javascript() {
+        assert_eq!(escape("a b%ダ"), "a%20b%25%u30C0");
+        assert_eq!(unescape("a%20b%25%u30C0%zz"), "a b%ダ%zz");
+    }
+}
@@ -0,0 +1,580 @@
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::events::Event;
+use crate::figures::{Kick, RotateDirection};
+use crate::glass::{DropKind, Dropped, GameOverReason, Glass, Held, MoveDirection};
+use crate::gravity::{GravityCurve, FRAME};
+use crate::lock_delay::LockDelay;
+use crate::scoring::{Scoring, ScoringRules};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GameState {
+    /// waiting for `start`
+    Ready,
+    /// counting down before the first figure spawns
+    Countdown { remaining: Duration },
+    Playing,
+    Paused,
+    /// cleared rows are being shown before the next figure spawns
+    LineClearAnimation { remaining: Duration },
+    GameOver { reason: GameOverReason },
+}
+
+/// Most frames a single `Game::update` plays, a second of game time.
+pub const MAX_UPDATE_FRAMES: u32 = 60;
+
+/// Game session driven by time: the glass plus the rules deciding when figures fall and lock.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Game {
+    pub glass: Glass,
+    state: GameState,
+    lock_delay: LockDelay,
+    gravity: GravityCurve,
+    scoring: Scoring,
+    countdown: Duration,
+    line_clear_delay: Duration,
+    /// time not yet consumed by whole frames
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "saved_frame_time"))]
+    frame_time: Duration,
+    /// fraction of a row the figure has fallen
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "saved_fall"))]
+    fall: f32,
+}
+
+/// Anything longer than a frame would replay frames once the game is restored.
+#[cfg(feature = "serde")]
+fn saved_frame_time<'de, D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Duration, D::Error> {
+    use serde::de::{Deserialize, Error as _};
+    let time = Duration::deserialize(deserializer)?;
+    if time < FRAME { Ok(time) } else { Err(D::Error::custom("frame time is longer than a frame")) }
+}
+
+#[cfg(feature = "serde")]
+fn saved_fall<'de, D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<f32, D::Error> {
+    use serde::de::{Deserialize, Error as _};
+    let fall = f32::deserialize(deserializer)?;
+    if (0.0 .. 1.0).contains(&fall) { Ok(fall) } else { Err(D::Error::custom("fall is not a fraction of a row")) }
+}
+
+impl Game {
+    pub fn new(glass: Glass) -> Game {
+        Game {
+            glass,
+            state: GameState::Ready,
+            lock_delay: LockDelay::guideline(),
+            gravity: GravityCurve::Guideline,
+            scoring: Scoring::new(ScoringRules::Guideline),
+            countdown: Duration::ZERO,
+            line_clear_delay: Duration::ZERO,
+            frame_time: Duration::ZERO,
+            fall: 0.0,
+        }
+    }
+
+    pub fn with_gravity(mut self, gravity: GravityCurve) -> Game {
+        self.gravity = gravity;
+        self
+    }
+
+    pub fn with_scoring(mut self, scoring: Scoring) -> Game {
+        self.scoring = scoring;
+        self
+    }
+
+    pub fn with_lock_delay(mut self, lock_delay: LockDelay) -> Game {
+        self.lock_delay = lock_delay;
+        self
+    }
+
+    /// Time between `start` and the first figure.
+    pub fn with_countdown(mut self, countdown: Duration) -> Game {
+        self.countdown = countdown;
+        self
+    }
+
+    /// Pause after clearing rows before the next figure spawns.
+    pub fn with_line_clear_delay(mut self, delay: Duration) -> Game {
+        self.line_clear_delay = delay;
+        self
+    }
+
+    pub fn scoring(&self) -> &Scoring {
+        &self.scoring
+    }
+
+    pub fn level(&self) -> u32 {
+        self.scoring.level()
+    }
+
+    pub fn state(&self) -> GameState {
+        self.state
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.state == GameState::Playing
+    }
+
+    /// Takes the events of both the glass and the game since the last call, oldest first.
+    pub fn drain_events(&mut self) -> std::vec::Drain<'_, Event> {
+        self.glass.drain_events()
+    }
+
+    /// Starts the countdown, or the game right away if there is none.
+    /// Fails unless the game is `Ready`.
+    pub fn start(&mut self) -> Result<()> {
+        let countdown = GameState::Countdown { remaining: self.countdown };
+        self.transition(GameState::Ready, countdown)?;
+        if self.countdown.is_zero() {
+            self.spawn_next();
+        }
+        Ok(())
+    }
+
+    pub fn pause(&mut self) -> Result<()> {
+        self.transition(GameState::Playing, GameState::Paused)
+    }
+
+    pub fn resume(&mut self) -> Result<()> {
+        self.transition(GameState::Paused, GameState::Playing)
+    }
+
+    /// Moves the game to the `to` state if it's in the `from` state.
+    fn transition(&mut self, from: GameState, to: GameState) -> Result<()> {
+        if self.state != from {
+            return Err(Error::IllegalTransition { from: self.state, to });
+        }
+        self.state = to;
+        Ok(())
+    }
+
+    /// Advances the game by `dt` in whole frames, applying gravity and lock delay.
+    /// Plays at most `MAX_UPDATE_FRAMES`, the time past them is dropped rather than caught up on later.
+    /// Returns the locks of the figures which ran out of lock delay, oldest first.
+    pub fn update(&mut self, dt: Duration) -> Vec<Dropped> {
+        let mut locks = Vec::new();
+        self.frame_time = self.frame_time.saturating_add(dt).min(FRAME * MAX_UPDATE_FRAMES);
+        while self.frame_time >= FRAME {
+            self.frame_time -= FRAME;
+            locks.extend(self.frame());
+        }
+        locks
+    }
+
+    fn frame(&mut self) -> Option<Dropped> {
+        match self.state {
+            GameState::Countdown { remaining } | GameState::LineClearAnimation { remaining } => {
+                let remaining = remaining.saturating_sub(FRAME);
+                self.state = match self.state {
+                    GameState::Countdown { .. } => GameState::Countdown { remaining },
+                    _ => GameState::LineClearAnimation { remaining },
+                };
+                if remaining.is_zero() {
+                    self.spawn_next();
+                }
+                None
+            },
+            GameState::Playing => self.fall(),
+            _ => None,
+        }
+    }
+
+    fn fall(&mut self) -> Option<Dropped> {
+        self.fall += self.gravity.cells_per_frame(self.level());
+        let rows = self.fall as usize;
+        self.fall -= rows as f32;
+        let moved = self.glass.fall_figure(DropKind::Gravity, rows);
+        if moved > 0 {
+            self.step_lock_delay();
+        }
+        if moved < rows {
+            self.fall = 0.0;
+        }
+
+        let grounded = self.glass.is_grounded();
+        if self.lock_delay.update(FRAME, grounded) {
+            Some(self.lock(DropKind::Gravity, 0))
+        } else {
+            None
+        }
+    }
+
+    pub fn move_figure(&mut self, direction: MoveDirection) -> bool {
+        let moved = self.is_playing() && self.glass.relocate_figure(direction);
+        if moved {
+            self.lock_delay.moved();
+        }
+        moved
+    }
+
+    pub fn rotate_figure(&mut self, direction: RotateDirection) -> Option<Kick> {
+        if !self.is_playing() {
+            return None;
+        }
+        let kick = self.glass.rotate_figure(direction);
+        if kick.is_some() {
+            self.lock_delay.moved();
+            self.step_lock_delay();
+        }
+        kick
+    }
+
+    /// Gravity and soft drops move the figure one row and leave locking to the lock delay,
+    /// hard drops lock immediately.
+    /// Returns `None` when the game isn't being played.
+    pub fn drop_figure(&mut self, kind: DropKind) -> Option<Dropped> {
+        if !self.is_playing() {
+            return None;
+        }
+
+        if kind == DropKind::Hard {
+            let distance = self.glass.fall_figure(kind, usize::MAX);
+            Some(self.lock(kind, distance))
+        } else {
+            let distance = self.glass.fall_figure(kind, 1);
+            if distance > 0 {
+                self.step_lock_delay();
+            }
+            let dropped = Dropped { kind, distance, locked: None };
+            self.score(&dropped);
+            Some(dropped)
+        }
+    }
+
+    pub fn soft_drop(&mut self) -> Option<Dropped> {
+        self.drop_figure(DropKind::Soft)
+    }
+
+    pub fn hard_drop(&mut self) -> Option<Dropped> {
+        self.drop_figure(DropKind::Hard)
+    }
+
+    /// Returns `None` when the game isn't being played or hold was already used for this figure.
+    pub fn hold_figure(&mut self) -> Option<Held> {
+        if !self.is_playing() {
+            return None;
+        }
+        let held = self.glass.hold_figure()?;
+        self.lock_delay.restart();
+        if let Some(reason) = held.game_over {
+            // the glass has already emitted the game over
+            self.state = GameState::GameOver { reason };
+        }
+        Some(held)
+    }
+
+    /// Pushes the stack up by rows of garbage, see `Glass::add_garbage`.
+    /// Returns why the game ended, if the garbage ended it.
+    pub fn add_garbage(&mut self, rows: usize, hole: usize) -> Option<GameOverReason> {
+        if !self.is_playing() {
+            return None;
+        }
+        let game_over = self.glass.add_garbage(rows, hole);
+        if let Some(reason) = game_over {
+            // the glass has already emitted the game over
+            self.state = GameState::GameOver { reason };
+        }
+        game_over
+    }
+
+    fn step_lock_delay(&mut self) {
+        if let Some(figure) = self.glass.figure {
+            self.lock_delay.stepped(figure.position.0);
+        }
+    }
+
+    fn spawn_next(&mut self) {
+        self.lock_delay.restart();
+        self.fall = 0.0;
+        if self.glass.next_figure() {
+            self.end(GameOverReason::BlockOut);
+        } else {
+            self.state = GameState::Playing;
+        }
+    }
+
+    fn end(&mut self, reason: GameOverReason) {
+        self.state = GameState::GameOver { reason };
+        self.glass.emit(Event::GameOver { reason });
+    }
+
+    fn score(&mut self, dropped: &Dropped) {
+        if self.scoring.add(dropped) {
+            self.glass.emit(Event::LevelUp { level: self.level() });
+        }
+    }
+
+    /// Compact binary form of the whole game, see `encoding`.
+    #[cfg(feature = "serde")]
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        crate::encoding::encode(self)
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Game> {
+        crate::encoding::decode(bytes)
+    }
+
+    fn lock(&mut self, kind: DropKind, distance: usize) -> Dropped {
+        let mut lock = self.glass.settle_figure();
+        if let Some(reason) = lock.game_over {
+            self.end(reason);
+        } else if !lock.rows.is_empty() && !self.line_clear_delay.is_zero() {
+            self.state = GameState::LineClearAnimation { remaining: self.line_clear_delay };
+        } else {
+            self.spawn_next();
+            if let GameState::GameOver { reason } = self.state {
+                lock.game_over = Some(reason);
+            }
+        }
+
+        let dropped = Dropped { kind, distance, locked: Some(lock) };
+        self.score(&dropped);
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::figures::{Figure, FigureRepr};
+    use crate::glass::Cell;
+    use crate::gravity::TWENTY_G;
+    use crate::lock_delay::LockReset;
+    use crate::scoring::LevelCounting;
+
+    fn game_with_cube() -> Game {
+        let mut game = Game::new(Glass::new(6, 4).with_seed(1)).with_gravity(GravityCurve::Constant(0.0));
+        game.start().unwrap();
+        game.glass.place(FigureRepr::new(Figure::Cube), (0, 0));
+        game
+    }
+
+    #[test]
+    fn grounded_figure_locks_after_delay() {
+        let mut game = game_with_cube();
+        game.soft_drop();
+        game.soft_drop();
+        assert!(game.glass.is_grounded());
+
+        assert!(game.update(FRAME * 24).is_empty());
+        let dropped = game.update(FRAME * 6);
+
+        assert!(matches!(&dropped[..], [Dropped { locked: Some(_), .. }]));
+        assert_eq!(game.glass[3][1 ..= 2], [Cell::Piece(Figure::Cube); 2]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn saved_game_keeps_score_and_timers() {
+        let mut game = game_with_cube();
+        game.hard_drop();
+        game.update(FRAME * 7);
+
+        let mut restored = Game::from_bytes(&game.to_bytes().unwrap()).unwrap();
+        assert_eq!((restored.state(), restored.scoring().score()), (game.state(), game.scoring().score()));
+
+        for game in [&mut game, &mut restored] {
+            game.soft_drop();
+            game.update(FRAME * 40);
+        }
+        assert_eq!(serde_json::to_value(&restored).unwrap(), serde_json::to_value(&game).unwrap());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn crafted_games_are_errors_or_harmless() {
+        let saved = serde_json::to_value(game_with_cube()).unwrap();
+        let restore = |craft: &dyn Fn(&mut serde_json::Value)| {
+            let mut saved = saved.clone();
+            craft(&mut saved);
+            serde_json::from_value::<Game>(saved)
+        };
+
+        assert!(restore(&|saved| saved["frame_time"] = serde_json::json!({ "secs": u64::MAX, "nanos": 0 })).is_err());
+        assert!(restore(&|saved| saved["fall"] = serde_json::json!(1e30)).is_err());
+
+        let mut game = restore(&|saved| {
+            saved["scoring"]["lines_per_level"] = serde_json::json!(0);
+            saved["scoring"]["score"] = serde_json::json!(u64::MAX);
+        }).unwrap();
+        game.hard_drop();
+        assert_eq!(game.scoring().score(), u64::MAX);
+    }
+
+    #[test]
+    fn moving_on_the_ground_delays_lock() {
+        let mut game = game_with_cube();
+        game.glass.figure = None;
+        game.glass.place(FigureRepr::new(Figure::Cube), (2, 0));
+
+        assert!(game.update(FRAME * 24).is_empty());
+        assert!(game.move_figure(MoveDirection::Right));
+        assert!(game.update(FRAME * 24).is_empty());
+        assert_eq!(game.update(FRAME * 6).len(), 1);
+    }
+
+    #[test]
+    fn controls_are_rejected_unless_playing() {
+        let mut game = Game::new(Glass::new(6, 8)).with_countdown(FRAME * 3);
+        assert!(!game.move_figure(MoveDirection::Left));
+        assert_eq!(game.start(), Ok(()));
+        assert_eq!(game.start(), Err(Error::IllegalTransition {
+            from: GameState::Countdown { remaining: FRAME * 3 },
+            to: GameState::Countdown { remaining: FRAME * 3 },
+        }));
+        assert_eq!(game.state(), GameState::Countdown { remaining: FRAME * 3 });
+
+        game.update(FRAME * 3);
+        assert_eq!(game.state(), GameState::Playing);
+        assert!(game.move_figure(MoveDirection::Left));
+
+        assert_eq!(game.pause(), Ok(()));
+        assert!(!game.move_figure(MoveDirection::Left));
+        assert_eq!(game.hard_drop(), None);
+        assert_eq!(game.resume(), Ok(()));
+        assert!(game.resume().is_err());
+        assert!(game.hard_drop().is_some());
+    }
+
+    #[test]
+    fn line_clear_animation_delays_next_figure() {
+        let mut game = game_with_cube().with_line_clear_delay(FRAME * 2);
+        game.glass[3].fill(Cell::Garbage);
+        game.glass[3][1] = Cell::Empty;
+        game.glass[3][2] = Cell::Empty;
+        game.glass.figure = None;
+        game.glass.place(FigureRepr::new(Figure::Cube), (0, 0));
+
+        assert_eq!(game.hard_drop().map(|d| d.locked.unwrap().rows), Some(vec![3]));
+        assert_eq!(game.state(), GameState::LineClearAnimation { remaining: FRAME * 2 });
+        assert!(game.glass.figure.is_none());
+
+        game.update(FRAME * 2);
+        assert_eq!(game.state(), GameState::Playing);
+        assert!(game.glass.figure.is_some());
+    }
+
+    #[test]
+    fn block_out_ends_the_game() {
+        let mut game = game_with_cube();
+        for row in 0 .. 4 {
+            game.glass[row].fill(Cell::Garbage);
+            game.glass[row][row % 2] = Cell::Empty;
+        }
+
+        let dropped = game.hard_drop().unwrap();
+
+        assert_eq!(dropped.game_over(), Some(GameOverReason::BlockOut));
+        assert_eq!(game.state(), GameState::GameOver { reason: GameOverReason::BlockOut });
+        assert_eq!(game.drain_events().next_back(), Some(Event::GameOver { reason: GameOverReason::BlockOut }));
+        assert!(game.update(FRAME).is_empty());
+    }
+
+    #[test]
+    fn holding_into_a_blocked_glass_ends_the_game() {
+        let mut game = Game::new(Glass::new(10, 20).with_seed(3)).with_gravity(GravityCurve::Constant(0.0));
+        game.start().unwrap();
+        for row in 0 .. 4 {
+            game.glass[row].fill(Cell::Garbage);
+        }
+        game.drain_events().for_each(drop);
+
+        let held = game.hold_figure().unwrap();
+
+        assert_eq!(held.game_over, Some(GameOverReason::BlockOut));
+        assert_eq!(game.state(), GameState::GameOver { reason: GameOverReason::BlockOut });
+        let game_overs = game.drain_events().filter(|event| matches!(event, Event::GameOver { .. })).count();
+        assert_eq!(game_overs, 1);
+        assert_eq!(game.hold_figure(), None);
+    }
+
+    #[test]
+    fn garbage_topping_out_ends_the_game() {
+        let mut game = game_with_cube();
+        game.hard_drop();
+        game.glass.figure = None;
+
+        assert_eq!(game.add_garbage(1, 5), None);
+        assert!(game.is_playing());
+        assert_eq!(game.add_garbage(2, 5), Some(GameOverReason::TopOut));
+        assert_eq!(game.state(), GameState::GameOver { reason: GameOverReason::TopOut });
+        assert_eq!(game.add_garbage(1, 5), None);
+    }
+
+    #[test]
+    fn clearing_enough_lines_levels_up() {
+        let scoring = Scoring::new(ScoringRules::Guideline).with_lines_per_level(1);
+        let mut game = game_with_cube().with_scoring(scoring);
+        game.glass[3].fill(Cell::Garbage);
+        game.glass[3][1] = Cell::Empty;
+        game.glass[3][2] = Cell::Empty;
+        game.drain_events().count();
+
+        game.hard_drop();
+
+        let events: Vec<_> = game.drain_events().collect();
+        assert!(events.contains(&Event::LinesCleared { rows: vec![3] }));
+        assert!(events.contains(&Event::LevelUp { level: 2 }));
+    }
+
+    #[test]
+    fn tgm_gravity_follows_tgm_levels() {
+
+        let scoring = Scoring::new(ScoringRules::Guideline).with_start_level(29).with_level_counting(LevelCounting::Tgm);
+        let mut game = game_with_cube().with_scoring(scoring).with_gravity(GravityCurve::Tgm);
+        assert_eq!(game.gravity.cells_per_frame(game.level()), 4.0 / 256.0);
+
+        game.hard_drop();
+
+        assert_eq!(game.level(), 30);
+        assert_eq!(game.gravity.cells_per_frame(game.level()), 6.0 / 256.0);
+    }
+
+    #[test]
+    fn one_update_returns_every_lock() {
+        let mut game = Game::new(Glass::new(10, 20).with_seed(4))
+            .with_gravity(GravityCurve::Constant(TWENTY_G))
+            .with_lock_delay(LockDelay::new(FRAME * 4, LockReset::Step));
+        game.start().unwrap();
+
+        // each figure falls and runs out of lock delay within four frames
+        let locks = game.update(FRAME * 20);
+
+        assert_eq!(locks.len(), 5);
+        assert!(locks.iter().all(|lock| lock.locked.is_some() && lock.kind == DropKind::Gravity));
+    }
+
+    #[test]
+    fn long_updates_are_capped() {
+        let mut game = game_with_cube().with_gravity(GravityCurve::Constant(1.0 / 1000.0));
+
+        game.update(Duration::MAX);
+        game.update(Duration::MAX);
+
+        assert_eq!(game.glass.figure.map(|f| f.position), Some((0, 0)));
+        assert!(game.frame_time < FRAME);
+        assert!((game.fall - 2.0 * MAX_UPDATE_FRAMES as f32 / 1000.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn gravity_moves_figure_by_fractions_of_a_row() {
+        let mut game = game_with_cube().with_gravity(GravityCurve::Constant(0.5));
+
+        game.update(FRAME);
+        assert_eq!(game.glass.figure.map(|f| f.position), Some((0, 0)));
+        game.update(FRAME);
+        assert_eq!(game.glass.figure.map(|f| f.position), Some((1, 0)));
+    }
+
+    #[test]
+    fn twenty_g_drops_to_the_bottom_in_one_frame() {
+        let mut game = game_with_cube().with_gravity(GravityCurve::Constant(TWENTY_G));
+
+        game.update(FRAME);
+        assert_eq!(game.glass.figure.map(|f| f.position), Some((2, 0)));
+        assert_eq!(game.drain_events().next_back(), Some(Event::Dropped { kind: DropKind::Gravity, distance: 2 }));
+    }
+}
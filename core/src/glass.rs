@@ -1,25 +1,218 @@
-use crate::figures::{Figure, FigureRepr};
-use rand::Rng;
+use std::borrow::Cow;
+
+use crate::error::{Error, Result};
+use crate::events::Event;
+use crate::figures::{Blocks, Figure, FigureRepr, Kick, Orientation, RotateDirection, RotationSystem, Srs, MAX_BLOCKS};
+use crate::pieces::{Piece, PieceSet};
+use crate::randomizer::{Bag, Randomizer};
+use crate::spawn::SpawnRule;
+#[cfg(feature = "serde")]
+use crate::figures::SavedRotationSystem;
+#[cfg(feature = "serde")]
+use crate::randomizer::SavedRandomizer;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// Most rows a glass can have, buffer rows included.
+pub const MAX_ROWS: usize = 1024;
+/// Most upcoming figures a restored glass can reveal.
+pub const MAX_PREVIEW: usize = 64;
 
 pub struct Glass {
     pub width: usize,
+    /// visible rows, the hidden buffer rows are on top of them
     pub height: usize,
-    map: Vec<bool>,
+    buffer: usize,
+    map: Vec<Cell>,
+    /// one bit per taken cell, bit 0 is the leftmost column
+    rows: Vec<u64>,
+    /// mask of a row with every cell taken
+    full_row: u64,
+    /// one bit per row whose cells were changed through `IndexMut`, their masks have to be rebuilt from the cells,
+    /// empty while no row is stale
+    stale_rows: Vec<u64>,
     pub figure: Option<FigureInGlass>,
+    /// mask of the figure, valid while the figure has these blocks
+    figure_mask: Option<(Blocks, PieceMask)>,
+    rotation_system: Box<dyn RotationSystem>,
+    /// overrides the spawn conventions of the rotation system
+    spawn_rule: Option<SpawnRule>,
+    randomizer: Box<dyn Randomizer>,
+    seed: u64,
+    rng: ChaCha8Rng,
+    /// pieces the randomizer deals from
+    pieces: PieceSet,
+    /// indices in `pieces` of the upcoming figures, the first one spawns next
+    queue: Vec<usize>,
+    preview: usize,
+    held: Option<usize>,
+    /// hold can be used once per figure taken from the queue
+    hold_used: bool,
+    /// kick of the rotation, if rotating was the last thing that moved the figure
+    last_kick: Option<Kick>,
+    /// consecutive locks clearing lines
+    combo: usize,
+    /// consecutive difficult line clears
+    difficult_clears: usize,
+    /// events not yet drained
+    events: Vec<Event>,
+}
+
+/// Content of a single cell of the glass.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Cell {
+    #[default]
+    Empty,
+    /// block left by a frozen figure
+    Piece(Figure),
+    /// block which didn't come from a figure, like rows sent by an opponent
+    Garbage,
+    /// block of a caller defined colour, 0xRRGGBB
+    Custom(u32),
+}
+
+impl Cell {
+    pub fn is_empty(self) -> bool {
+        self == Cell::Empty
+    }
+
+    pub fn is_filled(self) -> bool {
+        !self.is_empty()
+    }
 }
 
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FigureInGlass {
     pub figure: FigureRepr,
     pub position: (isize, isize),
+    /// index in the piece set of the glass, `None` for figures placed by hand
+    pub piece: Option<usize>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MoveDirection {
     Left,
     Right,
     Down
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DropKind {
+    /// one row, caused by the game itself
+    Gravity,
+    /// one row, requested by the player
+    Soft,
+    /// all the way down, locking the figure immediately
+    Hard,
+}
+
+/// Outcome of dropping the figure.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dropped {
+    pub kind: DropKind,
+    /// rows the figure moved down
+    pub distance: usize,
+    /// set when the figure was frozen and the next one spawned
+    pub locked: Option<Lock>,
+}
+
+impl Dropped {
+    pub fn game_over(&self) -> Option<GameOverReason> {
+        self.locked.as_ref().and_then(|lock| lock.game_over)
+    }
+}
+
+/// Outcome of swapping the figure with the held one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Held {
+    /// piece that went into the hold box
+    pub piece: usize,
+    /// set when the piece taken out of the hold box or the queue doesn't fit the glass
+    pub game_over: Option<GameOverReason>,
+}
+
+/// Why the game can't go on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GameOverReason {
+    /// the new figure overlaps blocks in the glass
+    BlockOut,
+    /// the figure locked entirely above the visible part of the glass
+    LockOut,
+    /// garbage pushed blocks or the figure past the top of the glass
+    TopOut,
+}
+
+/// T figure locked right after a rotation with three of the corners around its centre taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TSpin {
+    pub lines: usize,
+    /// only one of the two corners the T points at is taken
+    pub mini: bool,
+}
+
+/// Outcome of freezing the figure into the glass.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Lock {
+    /// filled rows removed from the glass, top to bottom
+    pub rows: Vec<usize>,
+    pub tspin: Option<TSpin>,
+    /// consecutive locks clearing lines ending with this one, 0 if no lines were cleared
+    pub combo: usize,
+    /// difficult clears (four lines or T-spins) right before this one, 0 unless this clear is difficult too
+    pub back_to_back: usize,
+    /// the glass is empty after cleaning the rows
+    pub all_clear: bool,
+    pub game_over: Option<GameOverReason>,
+}
+
+/// Blocks of a figure as one mask per row, so a position is tested with an AND per row.
+#[derive(Clone)]
+struct PieceMask {
+    /// bounds of the blocks relative to the figure position
+    left: isize,
+    right: isize,
+    top: isize,
+    bottom: isize,
+    /// rows of the blocks relative to the figure position, each with its blocks, bit 0 is the `left` column
+    rows: [(isize, u64); MAX_BLOCKS],
+    count: usize,
+}
+
+impl PieceMask {
+    fn new(figure: &FigureRepr) -> PieceMask {
+        let (mut left, mut top, mut right, mut bottom) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+        for &(x, y) in figure.blocks.iter() {
+            left = left.min(x);
+            right = right.max(x);
+            top = top.min(y);
+            bottom = bottom.max(y);
+        }
+        let (left, right, top, bottom) = (left as isize, right as isize, top as isize, bottom as isize);
+        let mut mask = PieceMask { left, right, top, bottom, rows: [(0, 0); MAX_BLOCKS], count: 0 };
+        // figures wider than a row mask never fit the glass, the bounds alone reject them
+        if right - left >= 64 {
+            return mask;
+        }
+        for &(x, y) in figure.blocks.iter() {
+            let bit = 1 << (x as isize - left);
+            match mask.rows[.. mask.count].iter_mut().find(|(row, _)| *row == y as isize) {
+                Some((_, bits)) => *bits |= bit,
+                None => {
+                    mask.rows[mask.count] = (y as isize, bit);
+                    mask.count += 1;
+                },
+            }
+        }
+        mask
+    }
+}
+
 impl MoveDirection {
 
     fn change_pos(self, (row, col): (isize, isize)) -> (isize, isize) {
@@ -33,127 +226,759 @@ impl MoveDirection {
 }
 
 impl Glass {
+    /// Panics if the size is invalid, see `try_new`.
     pub fn new(width: usize, height: usize) -> Glass {
-        Glass {
+        match Glass::try_new(width, height) {
+            Ok(glass) => glass,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Rows are kept as bit masks, so the glass has to be 1 to 64 cells wide,
+    /// and 1 to `MAX_ROWS` rows tall.
+    pub fn try_new(width: usize, height: usize) -> Result<Glass> {
+        let cells = width.checked_mul(height)
+            .filter(|_| width <= 64 && height <= MAX_ROWS)
+            .filter(|&cells| cells > 0)
+            .ok_or(Error::InvalidGlassSize { width, height })?;
+        let seed = rand::random();
+        Ok(Glass {
             width,
             height,
-            map: vec![false; width * height],
-            figure: None
+            buffer: 0,
+            map: vec![Cell::Empty; cells],
+            rows: vec![0; height],
+            full_row: u64::MAX.checked_shr(64 - width as u32).unwrap_or(0),
+            stale_rows: Vec::new(),
+            figure: None,
+            figure_mask: None,
+            rotation_system: Box::new(Srs),
+            spawn_rule: None,
+            randomizer: Box::new(Bag::seven()),
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            pieces: PieceSet::tetrominoes(),
+            queue: Vec::new(),
+            preview: 1,
+            held: None,
+            hold_used: false,
+            last_kick: None,
+            combo: 0,
+            difficult_clears: 0,
+            events: Vec::new(),
+        })
+    }
+
+    /// Panics if the glass gets too tall, see `try_with_buffer`.
+    pub fn with_buffer(self, rows: usize) -> Glass {
+        match self.try_with_buffer(rows) {
+            Ok(glass) => glass,
+            Err(error) => panic!("{}", error),
         }
     }
 
+    /// Hidden rows above the visible ones, figures spawn in them. Clears the glass.
+    /// All the rows together can't be more than `MAX_ROWS`.
+    pub fn try_with_buffer(mut self, rows: usize) -> Result<Glass> {
+        let total = self.height.checked_add(rows).filter(|&total| total <= MAX_ROWS)
+            .ok_or(Error::InvalidGlassSize { width: self.width, height: self.height.saturating_add(rows) })?;
+        self.buffer = rows;
+        self.map = vec![Cell::Empty; self.width * total];
+        self.rows = vec![0; total];
+        self.stale_rows.clear();
+        Ok(self)
+    }
+
+    pub fn buffer(&self) -> usize {
+        self.buffer
+    }
+
+    /// Rows of the glass to be shown, all the rows before them are hidden.
+    pub fn visible_rows(&self) -> ::std::ops::Range<usize> {
+        self.buffer .. self.total_height()
+    }
+
+    fn total_height(&self) -> usize {
+        self.buffer + self.height
+    }
+
+    /// Number of upcoming figures revealed by `preview`.
+    pub fn with_preview(mut self, preview: usize) -> Glass {
+        self.preview = preview;
+        self
+    }
+
+    /// Seed and the sequence of calls made on the glass fully determine the game.
+    pub fn with_seed(mut self, seed: u64) -> Glass {
+        self.seed = seed;
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn with_rotation_system<R: RotationSystem + 'static>(mut self, rotation_system: R) -> Glass {
+        self.rotation_system = Box::new(rotation_system);
+        self
+    }
+
+    /// Spawns figures by `rule` instead of the conventions of the rotation system.
+    pub fn with_spawn_rule(mut self, rule: SpawnRule) -> Glass {
+        self.spawn_rule = Some(rule);
+        self
+    }
+
+    pub fn spawn_rule(&self) -> SpawnRule {
+        self.spawn_rule.unwrap_or_else(|| self.rotation_system.spawn_rule())
+    }
+
+    /// Pieces dealt instead of the seven tetrominoes.
+    pub fn with_piece_set(mut self, pieces: PieceSet) -> Glass {
+        self.pieces = pieces;
+        self.queue.clear();
+        self.held = None;
+        self
+    }
+
+    pub fn piece_set(&self) -> &PieceSet {
+        &self.pieces
+    }
+
+    pub fn with_randomizer<R: Randomizer + 'static>(mut self, randomizer: R) -> Glass {
+        self.randomizer = Box::new(randomizer);
+        self
+    }
+
+    /// Takes the events which happened since the last call, oldest first.
+    /// Events pile up until drained.
+    pub fn drain_events(&mut self) -> std::vec::Drain<'_, Event> {
+        self.events.drain(..)
+    }
+
+    pub(crate) fn emit(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// Puts the figure at the position if it fits, emits no events.
     pub fn place(&mut self, figure: FigureRepr, (row, col): (isize, isize)) -> bool {
+        self.sync_rows();
         if !self.fit_glass(&figure, (row, col)) { false }
         else {
             self.figure = Some(FigureInGlass {
                 figure,
-                position: (row, col)
+                position: (row, col),
+                piece: None,
             });
+            self.update_figure_mask();
+            self.last_kick = None;
             true
         }
     }
 
     /// (row, col) - position in the glass. 0 row is the upper row.
-    fn fit_glass(&self, fmap: &FigureRepr, (row, col): (isize, isize)) -> bool {
+    fn fit_glass(&self, figure: &FigureRepr, position: (isize, isize)) -> bool {
+        self.fits(&PieceMask::new(figure), position)
+    }
+
+    /// Same as `fit_glass` for the figure of the mask, one AND per row.
+    fn fits(&self, mask: &PieceMask, (row, col): (isize, isize)) -> bool {
+        // saved glasses may hold any position
+        let left = col.saturating_add(mask.left);
+        if left < 0 || col.saturating_add(mask.right) >= self.width as isize
+            || row.saturating_add(mask.top) < 0 || row.saturating_add(mask.bottom) >= self.total_height() as isize {
+            return false;
+        }
+        let rows = &mask.rows[.. mask.count];
+        if self.stale_rows.is_empty() {
+            rows.iter().all(|&(y, bits)| self.rows[(row + y) as usize] & bits << left == 0)
+        } else {
+            rows.iter().all(|&(y, bits)| self.row_mask((row + y) as usize) & bits << left == 0)
+        }
+    }
+
+    /// Mask of the figure of the glass, the one kept from its last move unless its blocks changed since.
+    fn figure_mask(&self, figure: &FigureRepr) -> Cow<'_, PieceMask> {
+        match &self.figure_mask {
+            Some((blocks, mask)) if blocks[..] == figure.blocks[..] => Cow::Borrowed(mask),
+            _ => Cow::Owned(PieceMask::new(figure)),
+        }
+    }
 
-        for &(x, y) in fmap.blocks.iter() {
-            let glass_row = row + y as isize;
-            let glass_col = col + x as isize;
+    /// `fit_glass` for the figure of the glass.
+    fn figure_fits(&self, figure: &FigureRepr, position: (isize, isize)) -> bool {
+        self.fits(&self.figure_mask(figure), position)
+    }
 
-            let taken = || { self[glass_row as usize][glass_col as usize] };
+    /// Builds the mask of the figure if its blocks changed since the last one.
+    fn update_figure_mask(&mut self) {
+        if let Some(FigureInGlass { figure, .. }) = &self.figure {
+            if !matches!(&self.figure_mask, Some((blocks, _)) if *blocks == figure.blocks) {
+                self.figure_mask = Some((figure.blocks, PieceMask::new(figure)));
+            }
+        }
+    }
+
+    /// The cell is outside the glass or has a block in it.
+    fn is_taken(&self, row: isize, col: isize) -> bool {
+        self.is_outsize_glass(row, col) || self.row_mask(row as usize) >> col & 1 == 1
+    }
+
+    /// Taken cells of the row, up to date even when the row is stale.
+    fn row_mask(&self, row: usize) -> u64 {
+        if self.stale_rows.is_empty() { self.rows[row] } else { self.stale_row_mask(row) }
+    }
+
+    /// Kept out of `row_mask` so the masks of synced rows are read inline.
+    #[cold]
+    fn stale_row_mask(&self, row: usize) -> u64 {
+        if self.stale_rows[row / 64] >> (row % 64) & 1 == 1 { self.cells_mask(row) } else { self.rows[row] }
+    }
+
+    fn cells_mask(&self, row: usize) -> u64 {
+        self[row].iter().enumerate()
+            .filter(|(_, cell)| cell.is_filled())
+            .fold(0, |mask, (col, _)| mask | 1 << col)
+    }
 
-            if self.is_outsize_glass(glass_row, glass_col) || taken() {
-                return false;
+    /// Rebuilds the masks of the rows changed through `IndexMut`.
+    fn sync_rows(&mut self) {
+        let mut stale = ::std::mem::take(&mut self.stale_rows);
+        for (word, &bits) in stale.iter().enumerate() {
+            let mut bits = bits;
+            while bits != 0 {
+                let row = word * 64 + bits.trailing_zeros() as usize;
+                self.rows[row] = self.cells_mask(row);
+                bits &= bits - 1;
             }
         }
-        true
+        // keeps the allocation for the next stale rows
+        stale.clear();
+        self.stale_rows = stale;
+    }
+
+    /// Rebuilds the masks of all the rows from the cells.
+    fn rebuild_rows(&mut self) {
+        self.stale_rows.clear();
+        for row in 0 .. self.total_height() {
+            self.rows[row] = self.cells_mask(row);
+        }
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Result<Cell> {
+        if row >= self.total_height() || col >= self.width {
+            return Err(Error::OutOfRange { row, col });
+        }
+        Ok(self.map[row * self.width + col])
+    }
+
+    /// Emits no events.
+    pub fn set_cell(&mut self, row: usize, col: usize, cell: Cell) -> Result<()> {
+        if row >= self.total_height() || col >= self.width {
+            return Err(Error::OutOfRange { row, col });
+        }
+        self.map[row * self.width + col] = cell;
+        self.rows[row] = if cell.is_filled() { self.rows[row] | 1 << col } else { self.rows[row] & !(1 << col) };
+        Ok(())
+    }
+
+    /// The row has every cell taken.
+    pub fn is_row_filled(&self, row: usize) -> bool {
+        self.row_mask(row) == self.full_row
     }
 
     fn is_outsize_glass(&self, row: isize, col: isize) -> bool {
-        row < 0 || row >= self.height as isize || col < 0 || col >= self.width as isize
+        row < 0 || row >= self.total_height() as isize || col < 0 || col >= self.width as isize
     }
 
-    pub fn rotate_figure(&mut self) -> bool {
-        let orig_figure = self.figure.clone();
-        if let Some(FigureInGlass { mut figure, position }) = orig_figure {
-            figure.rotate();
-            if self.fit_glass(&figure, position) {
-                self.figure = Some(FigureInGlass{figure, position});
-                return true;
+    /// Rotates the figure according to the rotation system, trying wall kicks in order.
+    /// Returns the kick which succeeded or `None` if the figure can't be rotated.
+    pub fn rotate_figure(&mut self, direction: RotateDirection) -> Option<Kick> {
+        self.sync_rows();
+        if let Some(current @ FigureInGlass { figure, position: (row, col), piece }) = self.figure {
+            let piece = piece.and_then(|piece| self.pieces.get(piece));
+            let mut rotated = self.rotation_system.rotate(&figure, direction);
+            if let Some(rotations) = piece.and_then(|piece| piece.rotations) {
+                rotated.blocks = rotations[rotated.orientation() as usize];
+            }
+            let kicks: &[(i32, i32)] = match piece {
+                Some(piece) if !piece.kicks.is_empty() => &piece.kicks,
+                _ => self.rotation_system.kicks(&figure, figure.orientation(), rotated.orientation()),
+            };
+            let mask = PieceMask::new(&rotated);
+            let kicked = {
+                let fits = |position| self.fits(&mask, position);
+                let taken = |x: i32, y: i32| self.is_taken(row + y as isize, col + x as isize);
+                // kicks past the first one are only tried if the rotation system allows kicking at all
+                kicks.iter().enumerate()
+                    .take_while(|&(test, _)| test != 1 || self.rotation_system.can_kick(&rotated, &taken))
+                    .map(|(test, &(x, y))| (test, (x, y), (row - y as isize, col + x as isize)))
+                    .find(|&(_, _, position)| fits(position))
+            };
+
+            if let Some((test, (x, y), position)) = kicked {
+                self.figure = Some(FigureInGlass { figure: rotated, position, ..current });
+                self.figure_mask = Some((rotated.blocks, mask));
+                let tst = direction != RotateDirection::Half && x.abs() == 1 && y.abs() == 2;
+                let kick = Kick { test, offset: (x, -y), tst };
+                self.last_kick = Some(kick);
+                self.emit(Event::Rotated { direction, kick });
+                return Some(kick);
             }
         }
-        false
+        None
     }
 
     pub fn relocate_figure(&mut self, direction: MoveDirection) -> bool {
-        let orig_figure = self.figure.clone();
-        if let Some(FigureInGlass { figure, position }) = orig_figure {
+        let moved = self.shift_figure(direction);
+        if moved {
+            self.emit(Event::Moved { direction });
+        }
+        moved
+    }
+
+    fn shift_figure(&mut self, direction: MoveDirection) -> bool {
+        self.sync_rows();
+        let orig_figure = self.figure;
+        if let Some(current @ FigureInGlass { figure, position, .. }) = orig_figure {
             let new_position = direction.change_pos(position);
-            if self.fit_glass(&figure, new_position) {
-                self.figure = Some(FigureInGlass{figure, position: new_position});
+            if self.figure_fits(&figure, new_position) {
+                self.figure = Some(FigureInGlass { position: new_position, ..current });
+                self.last_kick = None;
                 return true;
             }
         }
         false
     }
 
+    /// Moves the figure down by up to `rows` rows without locking it, emitting a single drop event.
+    /// Returns the rows it moved.
+    pub fn fall_figure(&mut self, kind: DropKind, rows: usize) -> usize {
+        let mut distance = 0;
+        while distance < rows && self.shift_figure(MoveDirection::Down) {
+            distance += 1;
+        }
+        if distance > 0 {
+            self.emit(Event::Dropped { kind, distance });
+        }
+        distance
+    }
+
+    /// Moves the figure down, locking it when it can't fall any further.
+    pub fn drop_figure(&mut self, kind: DropKind) -> Dropped {
+        let mut dropped = Dropped { kind, distance: 0, locked: None };
+        if self.figure.is_none() {
+            return dropped;
+        }
+
+        let rows = if kind == DropKind::Hard { usize::MAX } else { 1 };
+        dropped.distance = self.fall_figure(kind, rows);
+
+        if kind == DropKind::Hard || dropped.distance == 0 {
+            dropped.locked = Some(self.lock_figure());
+        }
+        dropped
+    }
+
+    pub fn soft_drop(&mut self) -> Dropped {
+        self.drop_figure(DropKind::Soft)
+    }
+
+    pub fn hard_drop(&mut self) -> Dropped {
+        self.drop_figure(DropKind::Hard)
+    }
+
+    /// Freezes the figure, cleans filled rows and spawns the next figure.
+    pub fn lock_figure(&mut self) -> Lock {
+        let mut lock = self.settle_figure();
+        if lock.game_over.is_none() && self.next_figure() {
+            lock.game_over = Some(GameOverReason::BlockOut);
+        }
+        if let Some(reason) = lock.game_over {
+            self.emit(Event::GameOver { reason });
+        }
+        lock
+    }
+
+    /// Freezes the figure and cleans filled rows, leaving spawning the next figure to the caller.
+    pub fn settle_figure(&mut self) -> Lock {
+        self.sync_rows();
+        let mini = self.t_spin_mini();
+        let game_over = self.figure_coordinates()
+            .filter(|blocks| blocks.iter().all(|&(_, y)| y < self.buffer as i32))
+            .map(|_| GameOverReason::LockOut);
+        if let Some(blocks) = self.figure_coordinates() {
+            self.emit(Event::Locked { blocks });
+        }
+        self.freeze_figure();
+        let rows = self.clean_filled_rows();
+        let tspin = mini.map(|mini| TSpin { lines: rows.len(), mini });
+        if let Some(tspin) = tspin {
+            self.emit(Event::TSpin(tspin));
+        }
+        if !rows.is_empty() {
+            self.emit(Event::LinesCleared { rows: rows.clone() });
+        }
+
+        let mut back_to_back = 0;
+        if rows.is_empty() {
+            self.combo = 0;
+        } else {
+            self.combo += 1;
+            if rows.len() >= 4 || tspin.is_some() {
+                back_to_back = self.difficult_clears;
+                self.difficult_clears += 1;
+            } else {
+                self.difficult_clears = 0;
+            }
+        }
+        let all_clear = !rows.is_empty() && self.rows.iter().all(|&mask| mask == 0);
+
+        Lock { rows, tspin, combo: self.combo, back_to_back, all_clear, game_over }
+    }
+
+    /// Checks the figure for a T-spin using the 3-corner rule.
+    /// Returns whether it's a mini T-spin, or `None` if it's no T-spin at all.
+    fn t_spin_mini(&self) -> Option<bool> {
+        let kick = self.last_kick?;
+        let FigureInGlass { figure, position: (row, col), .. } = self.figure?;
+        if figure.kind() != Some(Figure::Base) {
+            return None;
+        }
+
+        let blocks = figure.blocks;
+        let neighbours = |(x, y): (i32, i32)| {
+            blocks.iter().filter(|&&(bx, by)| (bx - x).abs() + (by - y).abs() == 1).count()
+        };
+        let &(cx, cy) = blocks.iter().find(|&&b| neighbours(b) == 3)?;
+        // the nub is the only block around the centre without a block on the opposite side
+        let &(nx, ny) = blocks.iter()
+            .find(|&&(x, y)| (x, y) != (cx, cy) && !blocks.contains(&(2 * cx - x, 2 * cy - y)))?;
+        let (dx, dy) = (nx - cx, ny - cy);
+
+        let taken = |x: i32, y: i32| self.is_taken(row + (cy + y) as isize, col + (cx + x) as isize);
+        let front = [(dx - dy, dy + dx), (dx + dy, dy - dx)];
+        let back = [(-dx - dy, -dy + dx), (-dx + dy, -dy - dx)];
+        let front_taken = front.iter().filter(|&&(x, y)| taken(x, y)).count();
+        let back_taken = back.iter().filter(|&&(x, y)| taken(x, y)).count();
+
+        if front_taken + back_taken < 3 {
+            None
+        } else {
+            Some(front_taken < 2 && !kick.tst)
+        }
+    }
+
+    /// Leaves the figure's blocks in the glass as cells of its piece, or garbage if it has none.
+    /// Emits no events, `settle_figure` reports the lock.
     pub fn freeze_figure(&mut self) {
-        if let Some( FigureInGlass { figure, position: (row, col) } ) = self.figure.take() {
+        self.sync_rows();
+        if let Some( FigureInGlass { figure, position: (row, col), piece } ) = self.figure.take() {
+            let cell = match piece.and_then(|piece| self.pieces.get(piece)) {
+                Some(piece) => piece.cell,
+                None => figure.kind().map_or(Cell::Garbage, Cell::Piece),
+            };
             for &(x, y) in figure.blocks.iter() {
                 let glass_row = row + y as isize;
                 let glass_col = col + x as isize;
 
                 if !self.is_outsize_glass(glass_row, glass_col) {
-                    self[glass_row as usize][glass_col as usize] = true;
+                    self.map[glass_row as usize * self.width + glass_col as usize] = cell;
+                    self.rows[glass_row as usize] |= 1 << glass_col;
                 }
             }
         }
     }
 
-    pub fn clean_filled_rows(&mut self) {
-        for row in (0 .. self.height).rev() {
-            loop {
-                let filled_up = (0..self.width).all(|col| {
-                    self[row][col]
-                });
+    /// Pushes the stack up by `rows` rows of garbage with an empty cell at column `hole`,
+    /// the figure moves up as far as the garbage pushes it.
+    /// Returns `TopOut` if blocks or the figure were pushed past the top of the glass.
+    pub fn add_garbage(&mut self, rows: usize, hole: usize) -> Option<GameOverReason> {
+        self.sync_rows();
+        let total = self.total_height();
+        let rows = rows.min(total);
+        let mut top_out = self.rows[.. rows].iter().any(|&mask| mask != 0);
 
-                if !filled_up { break }
-                else {
-                    for r in (0.. row).rev() { //TODO can be optimized
-                        for col in 0..self.width {
-                            self[r+1][col] = self[r][col];
-                        }
-                    }
-                }
+        self.map.copy_within(rows * self.width .., 0);
+        self.rows.copy_within(rows .., 0);
+        for row in total - rows .. total {
+            for col in 0 .. self.width {
+                self.map[row * self.width + col] = if col == hole { Cell::Empty } else { Cell::Garbage };
+            }
+            self.rows[row] = self.full_row & !1u64.checked_shl(hole as u32).unwrap_or(0);
+        }
+
+        if let Some(current @ FigureInGlass { figure, position: (row, col), .. }) = self.figure {
+            let lifted = (0 ..= rows as isize)
+                .map(|up| (row - up, col))
+                .find(|&position| self.figure_fits(&figure, position));
+            match lifted {
+                Some(position) => self.figure = Some(FigureInGlass { position, ..current }),
+                None => top_out = true,
             }
         }
+
+        let game_over = if top_out { Some(GameOverReason::TopOut) } else { None };
+        if let Some(reason) = game_over {
+            self.emit(Event::GameOver { reason });
+        }
+        game_over
     }
 
+    /// Removes filled rows, moving the rows above them down.
+    /// Returns indices of the removed rows as they were before cleaning, top to bottom.
+    /// Emits no events, `settle_figure` reports the cleared rows.
+    pub fn clean_filled_rows(&mut self) -> Vec<usize> {
+        self.sync_rows();
+        let filled: Vec<usize> = (0 .. self.total_height())
+            .filter(|&row| self.rows[row] == self.full_row)
+            .collect();
+
+        // the rows between two filled ones move down as one run, by the number of filled rows under them
+        for (i, &row) in filled.iter().enumerate().rev() {
+            let start = if i == 0 { 0 } else { filled[i - 1] + 1 };
+            let shift = filled.len() - i;
+            self.map.copy_within(start * self.width .. row * self.width, (start + shift) * self.width);
+            self.rows.copy_within(start .. row, start + shift);
+        }
+        self.map[.. filled.len() * self.width].fill(Cell::Empty);
+        self.rows[.. filled.len()].fill(0);
+        filled
+    }
+
+    /// Upcoming pieces, the first one spawns next.
+    pub fn preview(&self) -> impl Iterator<Item = &Piece> {
+        self.queue.iter()
+            .take(self.preview)
+            .filter_map(|&piece| self.pieces.get(piece))
+    }
+
+    fn fill_queue(&mut self) {
+        while self.queue.len() <= self.preview {
+            let piece = self.randomizer.next_piece(&self.pieces, &mut self.rng);
+            self.queue.push(piece);
+        }
+    }
+
+    /// Spawns the next piece from the queue, returns `true` if it doesn't fit the glass.
     pub fn next_figure(&mut self) -> bool {
-        let figure = rand::thread_rng().gen::<Figure>();
-        let figure_repr = FigureRepr::new(figure);
+        self.fill_queue();
+        let piece = self.queue.remove(0);
+        self.fill_queue();
+        self.hold_used = false;
+        self.spawn_piece(piece)
+    }
 
-        let row = 0 - figure_repr.min_y();
-        let col = (self.width as isize) / 2 - figure_repr.center_x();
-        !self.place(figure_repr, (row, col))
+    pub fn held(&self) -> Option<&Piece> {
+        self.held.and_then(|piece| self.pieces.get(piece))
     }
 
-    pub fn figure_coordinates(&self) -> Option<[(i32, i32); 4]> {
-        if let Some(FigureInGlass{figure, position: (px, py)}) = self.figure {
-            let mut blocks = figure.blocks.clone();
+    /// Swaps the figure with the held one, or with the next one if nothing is held yet.
+    /// Returns `None` if hold was already used for this figure.
+    pub fn hold_figure(&mut self) -> Option<Held> {
+        let piece = self.figure.and_then(|f| f.piece);
+        match piece {
+            Some(piece) if !self.hold_used => {
+                self.figure = None;
+                self.emit(Event::Hold { held: piece });
+                let blocked = match self.held.replace(piece) {
+                    Some(held) => self.spawn_piece(held),
+                    None => self.next_figure(),
+                };
+                self.hold_used = true;
+                let game_over = if blocked { Some(GameOverReason::BlockOut) } else { None };
+                if let Some(reason) = game_over {
+                    self.emit(Event::GameOver { reason });
+                }
+                Some(Held { piece, game_over })
+            },
+            _ => None,
+        }
+    }
+
+    /// Spawns the piece of the set which is `figure`, returns `true` if it doesn't fit
+    /// or the set has no such piece.
+    pub fn spawn(&mut self, figure: Figure) -> bool {
+        match self.pieces.find(figure) {
+            Some(piece) => self.spawn_piece(piece),
+            None => true,
+        }
+    }
+
+    /// Places the piece at the top of the glass as the spawn rule says.
+    /// Returns `true` if it doesn't fit.
+    pub fn spawn_piece(&mut self, piece: usize) -> bool {
+        let rule = self.spawn_rule();
+        let (mut figure_repr, orientation, (dx, dy)) = match self.pieces.get(piece) {
+            Some(&Piece { figure: Some(figure), spawn_offset, .. }) =>
+                (self.rotation_system.spawn(figure), rule.orientation(figure), spawn_offset),
+            Some(&Piece { shape, spawn_offset, .. }) => (shape, Orientation::Spawn, spawn_offset),
+            None => return true,
+        };
+        let direction = match orientation {
+            Orientation::Spawn => None,
+            Orientation::Right => Some(RotateDirection::Clockwise),
+            Orientation::Reverse => Some(RotateDirection::Half),
+            Orientation::Left => Some(RotateDirection::CounterClockwise),
+        };
+        if let Some(direction) = direction {
+            figure_repr = self.rotation_system.rotate(&figure_repr, direction);
+        }
+
+        let row = if rule.in_buffer() {
+            // the lowest block right above the visible rows, unless the buffer is too small
+            (self.buffer as isize - 1 - figure_repr.max_y()).max(-figure_repr.min_y())
+        } else {
+            self.buffer as isize - figure_repr.min_y()
+        };
+        let column = match figure_repr.kind() {
+            Some(figure) => rule.column(figure, self.width),
+            None => rule.column_for_width(figure_repr.width(), self.width),
+        };
+        let col = column as isize - figure_repr.min_x();
+        let row = row + dy as isize;
+        let placed = self.place(figure_repr, (row, col + dx as isize));
+        if placed {
+            if let Some(figure) = &mut self.figure {
+                figure.piece = Some(piece);
+            }
+            // guideline figures drop a row right away, so they show up in the visible rows
+            if rule.in_buffer() && row + figure_repr.max_y() < self.buffer as isize {
+                self.shift_figure(MoveDirection::Down);
+            }
+            self.emit(Event::PieceSpawned { piece });
+        }
+        !placed
+    }
+
+    /// The figure can't move down any further.
+    pub fn is_grounded(&self) -> bool {
+        match &self.figure {
+            Some(FigureInGlass { figure, position: (row, col), .. }) => !self.figure_fits(figure, (row + 1, *col)),
+            None => false,
+        }
+    }
+
+    /// Position the figure would land at if hard dropped.
+    pub fn landing_position(&self) -> Option<(isize, isize)> {
+        self.figure.as_ref().map(|FigureInGlass { figure, position: (row, col), .. }| {
+            let mask = self.figure_mask(figure);
+            let mut row = *row;
+            while self.fits(&mask, (row + 1, *col)) {
+                row += 1;
+            }
+            (row, *col)
+        })
+    }
+
+    /// Block coordinates of the figure if it was hard dropped, in the same form as `figure_coordinates`.
+    pub fn ghost_coordinates(&self) -> Option<Blocks> {
+        let (row, col) = self.landing_position()?;
+        let mut blocks = self.figure?.figure.blocks;
+        for (x, y) in &mut blocks {
+            *x += col as i32;
+            *y += row as i32;
+        }
+        Some(blocks)
+    }
+
+    pub fn figure_coordinates(&self) -> Option<Blocks> {
+        if let Some(FigureInGlass { figure, position: (px, py), .. }) = self.figure {
+            let mut blocks = figure.blocks;
             for (x, y) in &mut blocks {
                 *x += py as i32;
                 *y += px as i32;
             }
             return Some(blocks);
         }
-        return None;
+        None
+    }
+
+    /// Glass drawn as text, one line per row with surrounding whitespace and blank lines ignored:
+    /// `.` is empty, `X` garbage, a tetromino letter like `T` a cell of that piece and `@` the figure.
+    /// The figure is the tetromino of the same shape in the first orientation matching it,
+    /// any other shape becomes a figure of its own. The glass has no buffer rows.
+    pub fn from_ascii(text: &str) -> Result<Glass> {
+        let lines: Vec<(usize, &str)> = text.lines().enumerate()
+            .map(|(number, line)| (number + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .collect();
+        let width = lines.first().map_or(0, |(_, line)| line.chars().count());
+        let mut glass = Glass::try_new(width, lines.len())?;
+
+        let mut cells = Vec::new();
+        for (row, &(line, text)) in lines.iter().enumerate() {
+            if text.chars().count() != width {
+                return Err(Error::Parse { line, reason: format!("row is not {} cells wide", width) });
+            }
+            for (col, c) in text.chars().enumerate() {
+                glass.map[row * width + col] = match c {
+                    '.' => Cell::Empty,
+                    'X' => Cell::Garbage,
+                    '@' => {
+                        cells.push((col as i32, row as i32));
+                        Cell::Empty
+                    }
+                    c => match Figure::ALL.iter().find(|figure| figure.letter() == c) {
+                        Some(&figure) => Cell::Piece(figure),
+                        None => return Err(Error::Parse { line, reason: format!("unknown cell '{}'", c) }),
+                    },
+                };
+            }
+        }
+        glass.rebuild_rows();
+
+        if !cells.is_empty() {
+            let (figure, piece) = match Glass::tetromino_like(&cells) {
+                Some((figure, repr)) => (repr, glass.pieces.find(figure)),
+                None => {
+                    let left = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+                    let top = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+                    let right = cells.iter().map(|&(x, _)| x).max().unwrap_or(0);
+                    let bottom = cells.iter().map(|&(_, y)| y).max().unwrap_or(0);
+                    let rows: Vec<String> = (top ..= bottom)
+                        .map(|y| (left ..= right).map(|x| if cells.contains(&(x, y)) { '@' } else { '.' }).collect())
+                        .collect();
+                    let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+                    (FigureRepr::from_rows(&rows)?, None)
+                }
+            };
+            let row = cells.iter().map(|&(_, y)| y).min().unwrap_or(0) as isize - figure.min_y();
+            let col = cells.iter().map(|&(x, _)| x).min().unwrap_or(0) as isize - figure.min_x();
+            glass.figure = Some(FigureInGlass { figure, position: (row, col), piece });
+        }
+        Ok(glass)
+    }
+
+    /// Tetromino rotated to cover exactly `cells` when moved there.
+    fn tetromino_like(cells: &[(i32, i32)]) -> Option<(Figure, FigureRepr)> {
+        fn normalized(cells: &[(i32, i32)]) -> Vec<(i32, i32)> {
+            let left = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+            let top = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+            let mut cells: Vec<_> = cells.iter().map(|&(x, y)| (x - left, y - top)).collect();
+            cells.sort_unstable();
+            cells
+        }
+
+        let shape = normalized(cells);
+        Figure::ALL.iter().find_map(|&figure| {
+            let mut repr = FigureRepr::new(figure);
+            for _ in 0 .. 4 {
+                if normalized(&repr.blocks) == shape {
+                    return Some((figure, repr));
+                }
+                repr.rotate();
+            }
+            None
+        })
     }
 }
 
 
+/// Panics if `row` is outside the glass, see `cell`.
 impl ::std::ops::Index<usize> for Glass {
-    type Output = [bool];
+    type Output = [Cell];
 
     fn index(&self, row: usize) -> &Self::Output {
         let start = row * self.width;
@@ -163,15 +988,172 @@ impl ::std::ops::Index<usize> for Glass {
 
 impl ::std::ops::IndexMut<usize> for Glass {
     fn index_mut(&mut self, row: usize) -> &mut Self::Output {
+        // rows outside the glass panic below
+        if row < self.total_height() {
+            if self.stale_rows.is_empty() {
+                self.stale_rows.resize(self.total_height().div_ceil(64), 0);
+            }
+            self.stale_rows[row / 64] |= 1 << (row % 64);
+        }
         let start = row * self.width;
         &mut self.map[start .. start + self.width]
     }
 }
 
+/// All the rows, buffer included, in the format read by `from_ascii`.
+/// Custom cells show as garbage.
+impl ::std::fmt::Display for Glass {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        let figure = self.figure_coordinates();
+        for row in 0 .. self.total_height() {
+            if row > 0 {
+                writeln!(f)?;
+            }
+            for col in 0 .. self.width {
+                let c = if figure.is_some_and(|blocks| blocks.contains(&(col as i32, row as i32))) {
+                    '@'
+                } else {
+                    match self[row][col] {
+                        Cell::Empty => '.',
+                        Cell::Garbage | Cell::Custom(_) => 'X',
+                        Cell::Piece(figure) => figure.letter(),
+                    }
+                };
+                write!(f, "{}", c)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Everything a glass is restored from, row masks are rebuilt from the cells.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SavedGlass {
+    width: usize,
+    height: usize,
+    buffer: usize,
+    map: Vec<Cell>,
+    figure: Option<FigureInGlass>,
+    rotation_system: SavedRotationSystem,
+    spawn_rule: Option<SpawnRule>,
+    randomizer: SavedRandomizer,
+    seed: u64,
+    rng: ChaCha8Rng,
+    pieces: PieceSet,
+    queue: Vec<usize>,
+    preview: usize,
+    held: Option<usize>,
+    hold_used: bool,
+    last_kick: Option<Kick>,
+    combo: usize,
+    difficult_clears: usize,
+    events: Vec<Event>,
+}
+
+/// Fails for rotation systems and randomizers which can't be saved.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Glass {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::Error as _;
+        let saved = SavedGlass {
+            width: self.width,
+            height: self.height,
+            buffer: self.buffer,
+            map: self.map.clone(),
+            figure: self.figure,
+            rotation_system: self.rotation_system.save()
+                .ok_or_else(|| S::Error::custom("rotation system can't be saved"))?,
+            spawn_rule: self.spawn_rule,
+            randomizer: self.randomizer.save()
+                .ok_or_else(|| S::Error::custom("randomizer can't be saved"))?,
+            seed: self.seed,
+            rng: self.rng.clone(),
+            pieces: self.pieces.clone(),
+            queue: self.queue.clone(),
+            preview: self.preview,
+            held: self.held,
+            hold_used: self.hold_used,
+            last_kick: self.last_kick,
+            combo: self.combo,
+            difficult_clears: self.difficult_clears,
+            events: self.events.clone(),
+        };
+        saved.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Glass {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        use serde::de::Error as _;
+        let saved = SavedGlass::deserialize(deserializer)?;
+        Glass::restore(saved).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Glass {
+    /// Saves come from files and the network, nothing is allocated or moved before they are checked.
+    fn restore(saved: SavedGlass) -> Result<Glass> {
+        let invalid = |reason: &str| Error::Encoding { reason: reason.to_string() };
+        let mut glass = Glass::try_new(saved.width, saved.height)
+            .and_then(|glass| glass.try_with_buffer(saved.buffer))
+            .map_err(|error| invalid(&error.to_string()))?;
+        if saved.map.len() != glass.map.len() {
+            return Err(invalid("cells don't match the glass size"));
+        }
+        if saved.preview > MAX_PREVIEW {
+            return Err(invalid("preview is too long"));
+        }
+        let count = saved.pieces.len();
+        let mut pieces = saved.figure.and_then(|figure| figure.piece).into_iter()
+            .chain(saved.held)
+            .chain(saved.queue.iter().copied());
+        if pieces.any(|piece| piece >= count) {
+            return Err(invalid("piece outside of the piece set"));
+        }
+
+        glass.map = saved.map;
+        glass.rebuild_rows();
+        if let Some(FigureInGlass { figure, position, .. }) = saved.figure {
+            if !glass.fit_glass(&figure, position) {
+                return Err(invalid("figure doesn't fit the glass"));
+            }
+        }
+        glass.figure = saved.figure;
+        glass.rotation_system = saved.rotation_system.restore();
+        glass.spawn_rule = saved.spawn_rule;
+        glass.randomizer = saved.randomizer.restore();
+        glass.seed = saved.seed;
+        glass.rng = saved.rng;
+        glass.pieces = saved.pieces;
+        glass.queue = saved.queue;
+        glass.preview = saved.preview;
+        glass.held = saved.held;
+        glass.hold_used = saved.hold_used;
+        glass.last_kick = saved.last_kick;
+        glass.combo = saved.combo;
+        glass.difficult_clears = saved.difficult_clears;
+        glass.events = saved.events;
+        Ok(glass)
+    }
+
+    /// Compact binary form of the glass, see `encoding`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        crate::encoding::encode(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Glass> {
+        crate::encoding::decode(bytes)
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::figures::{Figure, Nes, Orientation};
 
     use quickcheck::{Arbitrary, quickcheck};
     use quickcheck::Gen;
@@ -218,6 +1200,671 @@ mod tests {
 
             !glass.place(repr, (pos.0, pos.1))
         }
+
+        fn rotation_in_open_space_needs_no_kick(repr: FigureRepr) -> bool {
+            let mut glass = Glass::new(10, 10);
+            glass.place(repr, (4, 4));
+
+            let kick = glass.rotate_figure(RotateDirection::Clockwise);
+            glass.rotate_figure(RotateDirection::CounterClockwise);
+
+            kick.map(|k| k.test) == Some(0) && glass.figure.map(|f| f.figure.blocks) == Some(repr.blocks)
+        }
     }
-}
 
+    quickcheck! {
+
+        fn row_masks_agree_with_cells(blocks: Vec<(usize, usize)>, repr: FigureRepr, pos: FigurePos) -> bool {
+            let mut glass = Glass::new(10, 12);
+            for (col, row) in blocks {
+                glass[row % 12][col % 10] = Cell::Garbage;
+            }
+            let fit_cells = repr.blocks.iter()
+                .all(|&(x, y)| glass.cell((pos.0 + y as isize) as usize, (pos.1 + x as isize) as usize) == Ok(Cell::Empty));
+            let fit_stale_masks = glass.fit_glass(&repr, (pos.0, pos.1));
+            let filled_cells: Vec<bool> = (0 .. 12).map(|row| glass.is_row_filled(row)).collect();
+
+            glass.sync_rows();
+            let filled_rows: Vec<bool> = (0 .. 12).map(|row| glass.is_row_filled(row)).collect();
+
+            glass.fit_glass(&repr, (pos.0, pos.1)) == fit_cells && fit_stale_masks == fit_cells && filled_rows == filled_cells
+        }
+
+        fn piece_masks_agree_with_cells(blocks: Vec<(usize, usize)>, repr: FigureRepr, pos: FigurePos) -> bool {
+            let mut glass = Glass::new(10, 12);
+            for (col, row) in blocks {
+                glass.set_cell(row % 12, col % 10, Cell::Garbage).unwrap();
+            }
+            let fit_cells = repr.blocks.iter()
+                .all(|&(x, y)| glass.cell((pos.0 + y as isize) as usize, (pos.1 + x as isize) as usize) == Ok(Cell::Empty));
+
+            let fit_masks = glass.fit_glass(&repr, (pos.0, pos.1));
+            glass.figure_mask = Some((repr.blocks, PieceMask::new(&repr)));
+
+            fit_masks == fit_cells && glass.figure_fits(&repr, (pos.0, pos.1)) == fit_cells
+        }
+    }
+
+    quickcheck! {
+
+        fn same_seed_deals_same_figures(seed: u64) -> bool {
+            let mut first = Glass::new(10, 20).with_seed(seed);
+            let mut second = Glass::new(10, 20).with_randomizer(Bag::seven()).with_seed(seed);
+
+            (0 .. 20).all(|_| {
+                first.next_figure();
+                second.next_figure();
+                let same = first.figure_coordinates() == second.figure_coordinates();
+                first.figure = None;
+                second.figure = None;
+                same
+            }) && first.seed() == seed
+        }
+    }
+
+    #[test]
+    fn preview_shows_figures_spawned_next() {
+        let mut glass = Glass::new(10, 20).with_preview(5).with_seed(7);
+        glass.next_figure();
+        let preview: Vec<_> = glass.preview().map(|piece| piece.figure).collect();
+        assert_eq!(preview.len(), 5);
+
+        for figure in preview {
+            glass.figure = None;
+            glass.next_figure();
+            assert_eq!(glass.figure.and_then(|f| f.figure.kind()), figure);
+        }
+    }
+
+    #[test]
+    fn hold_swaps_figures_once_per_drop() {
+        let mut glass = Glass::new(10, 20).with_seed(3);
+        glass.next_figure();
+        let first = glass.figure.and_then(|f| f.figure.kind());
+        let second = glass.preview().next().and_then(|piece| piece.figure);
+
+        assert_eq!(glass.hold_figure().map(|held| held.game_over), Some(None));
+        assert_eq!(glass.held().and_then(|piece| piece.figure), first);
+        assert_eq!(glass.figure.and_then(|f| f.figure.kind()), second);
+        assert_eq!(glass.hold_figure(), None);
+
+        glass.figure = None;
+        glass.next_figure();
+        let third = glass.figure.and_then(|f| f.figure.kind());
+        assert!(glass.hold_figure().is_some());
+        assert_eq!(glass.held().and_then(|piece| piece.figure), third);
+        assert_eq!(glass.figure.and_then(|f| f.figure.kind()), first);
+        assert_eq!(glass.figure.map(|f| f.figure.orientation()), Some(Orientation::Spawn));
+    }
+
+    #[test]
+    fn holding_into_a_blocked_glass_is_a_block_out() {
+        let mut glass = Glass::new(10, 20).with_seed(3);
+        glass.next_figure();
+        let piece = glass.figure.and_then(|f| f.piece).unwrap();
+        for row in 0 .. 4 {
+            glass[row].fill(Cell::Garbage);
+        }
+        glass.drain_events().for_each(drop);
+
+        let held = glass.hold_figure();
+
+        assert_eq!(held, Some(Held { piece, game_over: Some(GameOverReason::BlockOut) }));
+        assert!(glass.figure.is_none());
+        assert_eq!(glass.drain_events().next_back(), Some(Event::GameOver { reason: GameOverReason::BlockOut }));
+    }
+
+    #[test]
+    fn hard_drop_locks_at_the_bottom() {
+        let mut glass = Glass::new(5, 6);
+        glass.place(FigureRepr::new(Figure::Line), (0, 0));
+
+        let dropped = glass.hard_drop();
+
+        assert_eq!(dropped, Dropped { kind: DropKind::Hard, distance: 4, locked: Some(Lock::default()) });
+        assert_eq!(glass[5][.. 4], [Cell::Piece(Figure::Line); 4]);
+        assert_eq!(glass[5][4], Cell::Empty);
+        assert!(glass.figure.is_some());
+    }
+
+    #[test]
+    fn soft_drop_moves_one_row_then_locks() {
+        let mut glass = Glass::new(4, 3);
+        glass.place(FigureRepr::new(Figure::Cube), (0, 0));
+
+        assert_eq!(glass.soft_drop(), Dropped { kind: DropKind::Soft, distance: 1, locked: None });
+        let dropped = glass.soft_drop();
+        assert_eq!((dropped.distance, dropped.locked.is_some()), (0, true));
+        assert_eq!(filled(&glass[2]), [false, true, true, false]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn saved_glass_plays_on_the_same() {
+        use crate::randomizer::History;
+
+        let mut glass = Glass::new(10, 20).with_buffer(2).with_preview(3).with_seed(9).with_randomizer(History::tgm());
+        glass.next_figure();
+        glass.hard_drop();
+        glass.next_figure();
+        glass.hold_figure();
+        glass[21][0] = Cell::Custom(7);
+
+        let json = serde_json::to_string(&glass).unwrap();
+        let bytes = glass.to_bytes().unwrap();
+        assert!(bytes.len() < json.len() / 2);
+
+        let mut glasses = vec![serde_json::from_str(&json).unwrap(), Glass::from_bytes(&bytes).unwrap(), glass];
+        assert!(glasses.iter().all(|glass| serde_json::to_string(glass).unwrap() == json));
+        for glass in &mut glasses {
+            for _ in 0 .. 10 {
+                glass.hard_drop();
+                glass.next_figure();
+            }
+        }
+        let json = serde_json::to_string(&glasses[2]).unwrap();
+        assert!(glasses.iter().all(|glass| serde_json::to_string(glass).unwrap() == json));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn unsaved_state_is_an_error() {
+        struct Fixed;
+        impl Randomizer for Fixed {
+            fn next_figure(&mut self, _rng: &mut dyn rand::RngCore) -> Figure {
+                Figure::Line
+            }
+        }
+        assert!(matches!(Glass::new(4, 4).with_randomizer(Fixed).to_bytes(), Err(Error::Encoding { .. })));
+
+        let mut bytes = Glass::new(4, 4).to_bytes().unwrap();
+        assert!(Glass::from_bytes(&bytes).is_ok());
+        bytes[0] += 1;
+        assert!(matches!(Glass::from_bytes(&bytes), Err(Error::Encoding { .. })));
+        assert!(matches!(Glass::from_bytes(&[]), Err(Error::Encoding { .. })));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn crafted_saves_are_errors() {
+        let mut glass = Glass::new(10, 20).with_buffer(2).with_seed(3);
+        glass.next_figure();
+        let saved = || -> SavedGlass { crate::encoding::decode(&glass.to_bytes().unwrap()).unwrap() };
+        let restore = |saved: SavedGlass| Glass::from_bytes(&crate::encoding::encode(&saved).unwrap());
+        assert!(restore(saved()).is_ok());
+
+        let crafted: Vec<fn(&mut SavedGlass)> = vec![
+            |saved| saved.height = usize::MAX / 2,
+            |saved| saved.width = usize::MAX,
+            |saved| saved.buffer = usize::MAX,
+            |saved| saved.preview = usize::MAX,
+            |saved| saved.figure.as_mut().unwrap().position = (isize::MAX, 0),
+            |saved| saved.figure.as_mut().unwrap().position.1 = -4,
+            |saved| saved.map.fill(Cell::Garbage),
+        ];
+        for (i, craft) in crafted.into_iter().enumerate() {
+            let mut saved = saved();
+            craft(&mut saved);
+            assert!(matches!(restore(saved), Err(Error::Encoding { .. })), "crafted save {}", i);
+        }
+    }
+
+    #[test]
+    fn mutating_calls_emit_events_in_order() {
+        let mut glass = Glass::new(4, 4).with_seed(1);
+        glass.place(FigureRepr::new(Figure::Cube), (0, 0));
+        glass.relocate_figure(MoveDirection::Right);
+        glass.hard_drop();
+        let spawned = glass.figure.and_then(|f| f.piece).unwrap();
+        glass.hold_figure();
+
+        let events: Vec<_> = glass.drain_events().collect();
+        assert_eq!(events[.. 2], [
+            Event::Moved { direction: MoveDirection::Right },
+            Event::Dropped { kind: DropKind::Hard, distance: 2 },
+        ]);
+        assert!(matches!(&events[2], Event::Locked { blocks } if blocks.iter().all(|&(x, y)| x >= 2 && y >= 2)));
+        assert_eq!(events[3 ..= 4], [Event::PieceSpawned { piece: spawned }, Event::Hold { held: spawned }]);
+        assert_eq!(glass.drain_events().count(), 0);
+    }
+
+    #[test]
+    fn stale_rows_past_the_first_mask_word_are_rebuilt() {
+        let mut glass = Glass::new(6, 100).with_buffer(30);
+        glass[129][3] = Cell::Garbage;
+        glass[70][2] = Cell::Garbage;
+        glass.place(FigureRepr::new(Figure::Cube), (0, 0));
+
+        assert_eq!(glass.landing_position(), Some((68, 0)));
+        glass.hard_drop();
+        assert_eq!(glass[69][1 ..= 2], [Cell::Piece(Figure::Cube); 2]);
+        glass[70][2] = Cell::Empty;
+        glass.place(FigureRepr::new(Figure::Cube), (0, 2));
+        assert_eq!(glass.landing_position(), Some((127, 2)));
+    }
+
+    #[test]
+    fn clean_filled_rows_reports_removed_rows() {
+        let mut glass = Glass::new(3, 5);
+        for row in [1, 3, 4] {
+            glass[row].fill(Cell::Garbage);
+        }
+        glass[2][0] = Cell::Piece(Figure::LeftZig);
+        glass[0][2] = Cell::Custom(0xff0000);
+
+        assert_eq!(glass.clean_filled_rows(), vec![1, 3, 4]);
+        assert_eq!(glass[4], [Cell::Piece(Figure::LeftZig), Cell::Empty, Cell::Empty]);
+        assert_eq!(glass[3], [Cell::Empty, Cell::Empty, Cell::Custom(0xff0000)]);
+        assert!((0 .. 3).all(|row| glass[row].iter().all(|cell| cell.is_empty())));
+    }
+
+    #[test]
+    fn invalid_sizes_and_cells_are_errors() {
+        assert_eq!(Glass::try_new(0, 20).err(), Some(Error::InvalidGlassSize { width: 0, height: 20 }));
+        assert_eq!(Glass::try_new(65, 20).err(), Some(Error::InvalidGlassSize { width: 65, height: 20 }));
+        assert_eq!(Glass::try_new(64, usize::MAX / 8).err(), Some(Error::InvalidGlassSize { width: 64, height: usize::MAX / 8 }));
+        assert!(Glass::try_new(10, MAX_ROWS + 1).is_err());
+        assert!(Glass::new(10, 20).try_with_buffer(usize::MAX).is_err());
+        assert_eq!(Glass::new(10, 20).try_with_buffer(MAX_ROWS - 20).map(|glass| glass.total_height()), Ok(MAX_ROWS));
+
+        let mut glass = Glass::try_new(4, 4).unwrap().with_buffer(1);
+        assert_eq!(glass.set_cell(4, 3, Cell::Garbage), Ok(()));
+        assert_eq!(glass.cell(4, 3), Ok(Cell::Garbage));
+        assert_eq!(glass.cell(5, 0), Err(Error::OutOfRange { row: 5, col: 0 }));
+        assert_eq!(glass.set_cell(0, 4, Cell::Garbage), Err(Error::OutOfRange { row: 0, col: 4 }));
+        assert_eq!(glass.landing_position(), None);
+        assert!(!glass.is_row_filled(4));
+    }
+
+    #[test]
+    fn widest_glass_clears_full_rows() {
+        let mut glass = Glass::new(64, 4);
+        glass[3].fill(Cell::Garbage);
+        glass[2][63] = Cell::Garbage;
+
+        assert!(glass.is_row_filled(3));
+        assert_eq!(glass.clean_filled_rows(), vec![3]);
+        assert!(!glass.is_row_filled(3));
+        assert_eq!(glass[3][63], Cell::Garbage);
+        assert!(glass.place(FigureRepr::new(Figure::Cube), (0, 61)));
+        assert!(!glass.relocate_figure(MoveDirection::Right));
+    }
+
+    fn filled(row: &[Cell]) -> Vec<bool> {
+        row.iter().map(|cell| cell.is_filled()).collect()
+    }
+
+    #[test]
+    fn t_spin_double_is_detected() {
+        let mut glass = Glass::from_ascii("
+            ......
+            ......
+            ..X...
+            XX...X
+            XXX.XX
+        ").unwrap();
+        let mut base = FigureRepr::new(Figure::Base);
+        base.rotate();
+        assert!(glass.place(base, (2, 2)));
+        assert!(glass.rotate_figure(RotateDirection::Clockwise).is_some());
+
+        let lock = glass.hard_drop().locked.unwrap();
+
+        assert_eq!(lock.rows, vec![3, 4]);
+        assert_eq!(lock.tspin, Some(TSpin { lines: 2, mini: false }));
+        let events: Vec<_> = glass.drain_events().skip_while(|e| !matches!(e, Event::TSpin(_))).take(2).collect();
+        assert_eq!(events, [Event::TSpin(TSpin { lines: 2, mini: false }), Event::LinesCleared { rows: vec![3, 4] }]);
+    }
+
+    #[test]
+    fn t_spin_needs_rotation_last() {
+        let mut glass = Glass::from_ascii("
+            ......
+            ......
+            ..X...
+            XX@@@X
+            XXX@XX
+        ").unwrap();
+
+        let lock = glass.hard_drop().locked.unwrap();
+
+        assert_eq!(lock.rows, vec![3, 4]);
+        assert_eq!(lock.tspin, None);
+    }
+
+    #[test]
+    fn tst_kick_makes_a_proper_t_spin() {
+        let mut glass = Glass::from_ascii("
+            .X@.
+            .@@@
+            X.X.
+            ....
+            X...
+            ....
+        ").unwrap();
+        let kick = glass.rotate_figure(RotateDirection::Clockwise);
+        assert_eq!(kick, Some(Kick { test: 4, offset: (-1, 2), tst: true }));
+
+        // only one of the corners the T points at is taken
+        assert_eq!(glass.settle_figure().tspin, Some(TSpin { lines: 0, mini: false }));
+    }
+
+    #[test]
+    fn half_turn_kicks_keep_a_mini_t_spin() {
+        let mut glass = Glass::from_ascii("
+            ....
+            ..X.
+            ....
+            X@X.
+            X@@.
+            .@..
+        ").unwrap();
+        // the fifth 180 degree kick is two rows up
+        let kick = glass.rotate_figure(RotateDirection::Half);
+        assert_eq!(kick, Some(Kick { test: 4, offset: (0, -2), tst: false }));
+
+        assert_eq!(glass.settle_figure().tspin, Some(TSpin { lines: 0, mini: true }));
+    }
+
+    #[test]
+    fn t_spin_mini_is_detected() {
+        let mut glass = Glass::from_ascii("
+            ....
+            .@..
+            @@@.
+            .X..
+        ").unwrap();
+        // kicked against the left wall, pointing right with only one corner in front taken
+        assert_eq!(glass.rotate_figure(RotateDirection::Clockwise).map(|k| k.test), Some(1));
+
+        let lock = glass.hard_drop().locked.unwrap();
+
+        assert_eq!(lock.tspin, Some(TSpin { lines: 0, mini: true }));
+    }
+
+    #[test]
+    fn combo_back_to_back_and_all_clear_are_tracked() {
+        let mut glass = Glass::from_ascii("
+            ......
+            ......
+            ......
+            X.....
+            XXXXX.
+            XXXXX.
+            XXXXX.
+            XXXXX.
+        ").unwrap();
+        let mut line = FigureRepr::new(Figure::Line);
+        line.rotate();
+
+        glass.place(line, (0, 3));
+        let first = glass.hard_drop().locked.unwrap();
+        assert_eq!((first.rows.len(), first.combo, first.back_to_back, first.all_clear), (4, 1, 0, false));
+
+        for row in 4 .. 8 {
+            glass[row].fill(Cell::Garbage);
+            glass[row][5] = Cell::Empty;
+        }
+        glass.place(line, (0, 3));
+        let second = glass.hard_drop().locked.unwrap();
+        assert_eq!((second.rows.len(), second.combo, second.back_to_back, second.all_clear), (4, 2, 1, true));
+
+        glass.place(line, (0, 3));
+        let miss = glass.hard_drop().locked.unwrap();
+        assert_eq!((miss.rows.len(), miss.combo, miss.back_to_back), (0, 0, 0));
+    }
+
+    #[test]
+    fn block_out_ends_the_game() {
+        let mut glass = Glass::new(4, 4);
+        glass[1].fill(Cell::Garbage);
+        glass[1][0] = Cell::Empty;
+        // a flat I spawns in the top row alone
+        glass[0][3] = Cell::Garbage;
+        glass.place(FigureRepr::new(Figure::Cube), (2, 0));
+
+        let lock = glass.lock_figure();
+
+        assert_eq!(lock.game_over, Some(GameOverReason::BlockOut));
+        assert!(glass.figure.is_none());
+    }
+
+    #[test]
+    fn lock_above_the_glass_ends_the_game() {
+        let mut glass = Glass::new(4, 4);
+        glass.figure = Some(FigureInGlass { figure: FigureRepr::new(Figure::Cube), position: (-2, 0), piece: None });
+        assert_eq!(glass.settle_figure().game_over, Some(GameOverReason::LockOut));
+    }
+
+    #[test]
+    fn garbage_pushes_the_stack_and_the_figure_up() {
+        let mut glass = Glass::new(4, 6);
+        glass[5][0] = Cell::Piece(Figure::Cube);
+        glass.place(FigureRepr::new(Figure::Cube), (3, 0));
+
+        assert_eq!(glass.add_garbage(2, 1), None);
+        assert_eq!(glass[3], [Cell::Piece(Figure::Cube), Cell::Empty, Cell::Empty, Cell::Empty]);
+        assert_eq!(glass[5], [Cell::Garbage, Cell::Empty, Cell::Garbage, Cell::Garbage]);
+        assert_eq!(glass.figure.map(|f| f.position), Some((2, 0)));
+        assert!(glass.is_grounded());
+    }
+
+    #[test]
+    fn garbage_past_the_top_is_a_top_out() {
+        let mut glass = Glass::new(4, 4).with_buffer(2);
+        glass[1][3] = Cell::Garbage;
+        assert_eq!(glass.add_garbage(1, 0), None);
+        assert_eq!(glass.add_garbage(1, 0), Some(GameOverReason::TopOut));
+        assert_eq!(glass.drain_events().next_back(), Some(Event::GameOver { reason: GameOverReason::TopOut }));
+
+        // the figure can't be pushed out of the glass either
+        let mut glass = Glass::new(4, 4);
+        glass.place(FigureRepr::new(Figure::Cube), (2, 0));
+        assert_eq!(glass.add_garbage(2, 3), None);
+        assert_eq!(glass.add_garbage(1, 3), Some(GameOverReason::TopOut));
+    }
+
+    #[test]
+    fn pentominoes_spawn_rotate_and_lock() {
+        let mut glass = Glass::new(10, 20).with_piece_set(PieceSet::pentominoes()).with_seed(2);
+        assert!(glass.spawn(Figure::Base));
+
+        // the I pentomino centred rounding to the left
+        assert!(!glass.spawn_piece(2));
+        assert_eq!(glass.figure_coordinates().unwrap(), [(2, 0), (3, 0), (4, 0), (5, 0), (6, 0)]);
+        assert!(glass.rotate_figure(RotateDirection::Clockwise).is_some());
+        assert_eq!(glass.figure_coordinates().map(|blocks| blocks.len()), Some(5));
+
+        glass.hard_drop();
+        let column: Vec<Cell> = (15 .. 20).map(|row| glass[row][4]).collect();
+        assert_eq!(column, [Cell::Custom(0x00c8c8); 5]);
+
+        for _ in 0 .. 20 {
+            glass.figure = None;
+            glass.next_figure();
+            assert_eq!(glass.figure_coordinates().map(|blocks| blocks.len()), Some(5));
+        }
+    }
+
+    #[test]
+    fn loaded_pieces_rotate_through_their_drawings() {
+        let set = PieceSet::parse("piece L\nspawn 0 2\nkicks 0,0 -1,0\nX.\nXX\n\nXX\nX.\n\nXX\n.X\n\n.X\nXX").unwrap();
+        let mut glass = Glass::new(4, 6).with_piece_set(set);
+
+        assert!(!glass.spawn_piece(0));
+        assert_eq!(glass.figure_coordinates().unwrap(), [(1, 2), (1, 3), (2, 3)]);
+
+        glass.relocate_figure(MoveDirection::Right);
+        glass[2][3] = Cell::Garbage;
+        // the drawing of the right orientation doesn't fit, the second kick moves it left
+        assert_eq!(glass.rotate_figure(RotateDirection::Clockwise), Some(Kick { test: 1, offset: (-1, 0), tst: false }));
+        assert_eq!(glass.figure_coordinates().unwrap(), [(1, 2), (2, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn figures_spawn_in_the_buffer() {
+        let mut glass = Glass::new(10, 20).with_buffer(20);
+        assert_eq!(glass.visible_rows(), 20 .. 40);
+
+        glass.spawn(Figure::Base);
+        assert_eq!(glass.figure_coordinates().unwrap(), [(4, 19), (3, 20), (4, 20), (5, 20)]);
+
+        glass.hard_drop();
+        assert_eq!(glass[39][3 ..= 5], [Cell::Piece(Figure::Base); 3]);
+    }
+
+    #[test]
+    fn spawned_figures_show_in_the_visible_rows() {
+        for rule in [SpawnRule::Guideline, SpawnRule::Tgm, SpawnRule::Nes] {
+            for &figure in Figure::ALL.iter() {
+                let mut glass = Glass::new(10, 20).with_buffer(20).with_spawn_rule(rule);
+                assert!(!glass.spawn(figure));
+                let visible = glass.visible_rows();
+                assert!(glass.figure_coordinates().unwrap().iter().any(|&(_, y)| visible.contains(&(y as usize))));
+            }
+        }
+    }
+
+    #[test]
+    fn failed_spawn_keeps_the_figure() {
+        let mut glass = Glass::new(10, 20).with_buffer(2);
+        assert!(!glass.spawn(Figure::Line));
+        let figure = glass.figure.unwrap();
+        glass[0].fill(Cell::Garbage);
+        glass[1].fill(Cell::Garbage);
+
+        assert!(glass.spawn(Figure::Cube));
+        assert_eq!(glass.figure.map(|f| (f.piece, f.position)), Some((figure.piece, figure.position)));
+    }
+
+    #[test]
+    fn classic_rules_spawn_flat_side_up_in_the_visible_rows() {
+        let mut glass = Glass::new(10, 20).with_buffer(2).with_rotation_system(Nes);
+        glass.spawn(Figure::Base);
+        let mut blocks = glass.figure_coordinates().unwrap();
+        blocks.sort_by_key(|&(x, y)| (y, x));
+        assert_eq!(blocks, [(4, 2), (5, 2), (6, 2), (5, 3)]);
+
+        let mut glass = Glass::new(10, 20).with_rotation_system(Nes).with_spawn_rule(SpawnRule::Guideline);
+        glass.spawn(Figure::Base);
+        let mut blocks = glass.figure_coordinates().unwrap();
+        blocks.sort_by_key(|&(x, y)| (y, x));
+        assert_eq!(blocks, [(4, 0), (3, 1), (4, 1), (5, 1)]);
+    }
+
+    #[test]
+    fn locking_in_the_buffer_keeps_blocks() {
+        let mut glass = Glass::new(4, 4).with_buffer(2);
+        glass.figure = Some(FigureInGlass { figure: FigureRepr::new(Figure::Cube), position: (0, 0), piece: None });
+        assert_eq!(glass.settle_figure().game_over, Some(GameOverReason::LockOut));
+        assert_eq!(glass[1][1 ..= 2], [Cell::Piece(Figure::Cube); 2]);
+
+        glass.figure = Some(FigureInGlass { figure: FigureRepr::new(Figure::Cube), position: (1, 0), piece: None });
+        assert_eq!(glass.settle_figure().game_over, None);
+    }
+
+    #[test]
+    fn ghost_lands_on_the_stack() {
+        let mut glass = Glass::new(4, 6);
+        glass[5][1] = Cell::Garbage;
+        glass.place(FigureRepr::new(Figure::Cube), (0, 0));
+
+        assert_eq!(glass.ghost_coordinates().unwrap(), [(1, 3), (2, 3), (1, 4), (2, 4)]);
+
+        glass.hard_drop();
+        assert_eq!(filled(&glass[4]), [false, true, true, false]);
+        assert_eq!(filled(&glass[3]), [false, true, true, false]);
+    }
+
+    #[test]
+    fn line_kicks_off_the_left_wall() {
+        let mut glass = Glass::new(10, 10);
+        let mut line = FigureRepr::new(Figure::Line);
+        line.rotate_to(RotateDirection::CounterClockwise);
+        // vertical line occupies column 1 of its box, put it against the left wall
+        assert!(glass.place(line, (3, -1)));
+
+        let kick = glass.rotate_figure(RotateDirection::Clockwise);
+
+        assert_eq!(kick, Some(Kick { test: 1, offset: (1, 0), tst: false }));
+        assert_eq!(glass.figure_coordinates().unwrap(), [(0, 4), (1, 4), (2, 4), (3, 4)]);
+    }
+
+    #[test]
+    fn nes_rotation_never_kicks() {
+        let mut glass = Glass::new(10, 10).with_rotation_system(Nes);
+        let line = Nes.rotate(&Nes.spawn(Figure::Line), RotateDirection::Clockwise);
+        // vertical line sits in column 2 of its box, put it against the right wall
+        assert!(glass.place(line, (3, 7)));
+
+        assert_eq!(glass.rotate_figure(RotateDirection::Clockwise), None);
+
+        glass.relocate_figure(MoveDirection::Left);
+        glass.relocate_figure(MoveDirection::Left);
+        assert_eq!(glass.rotate_figure(RotateDirection::Clockwise), Some(Kick { test: 0, offset: (0, 0), tst: false }));
+    }
+
+    #[test]
+    fn base_rotation_fails_when_no_kick_fits() {
+        let mut glass = Glass::new(3, 2);
+        assert!(glass.place(FigureRepr::new(Figure::Base), (0, 0)));
+
+        assert_eq!(glass.rotate_figure(RotateDirection::Clockwise), None);
+        assert_eq!(glass.figure.map(|f| f.position), Some((0, 0)));
+    }
+
+    #[test]
+    fn ascii_round_trips_through_display() {
+        let text = "..@...\n.@@@..\nX...TT\nIIIIXX";
+        let glass = Glass::from_ascii(text).unwrap();
+
+        assert_eq!(glass.to_string(), text);
+        assert_eq!(glass[3][0], Cell::Piece(Figure::Line));
+        assert_eq!(glass.figure.map(|f| (f.position, f.piece)), Some(((0, 1), glass.piece_set().find(Figure::Base))));
+    }
+
+    #[test]
+    fn ascii_figure_takes_the_orientation_it_is_drawn_in() {
+        let glass = Glass::from_ascii("
+            .@.
+            .@.
+            .@@
+            ...
+        ").unwrap();
+        let figure = glass.figure.unwrap();
+
+        assert_eq!(figure.figure.orientation(), Orientation::Right);
+        let mut blocks: Vec<_> = glass.figure_coordinates().unwrap().into();
+        blocks.sort_unstable();
+        assert_eq!(blocks, [(1, 0), (1, 1), (1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn ascii_figure_of_any_shape() {
+        let mut glass = Glass::from_ascii("
+            @.@..
+            @@@..
+            .....
+        ").unwrap();
+
+        assert_eq!(glass.figure.map(|f| f.piece), Some(None));
+        glass.relocate_figure(MoveDirection::Down);
+        assert_eq!(glass.to_string(), ".....\n@.@..\n@@@..");
+    }
+
+    #[test]
+    fn ascii_errors_point_at_the_line() {
+        assert_eq!(Glass::from_ascii("...\n..\n").err(), Some(Error::Parse { line: 2, reason: "row is not 3 cells wide".into() }));
+        assert!(matches!(Glass::from_ascii("\n...\n.?.\n"), Err(Error::Parse { line: 3, .. })));
+        assert!(matches!(Glass::from_ascii("  \n"), Err(Error::InvalidGlassSize { .. })));
+    }
+
+    #[test]
+    fn display_shows_the_buffer_and_custom_cells_as_garbage() {
+        let mut glass = Glass::new(3, 2).with_buffer(1);
+        glass[2][0] = Cell::Custom(0xff0000);
+        glass[2][2] = Cell::Piece(Figure::LeftZig);
+
+        assert_eq!(glass.to_string(), "...\n...\nX.Z");
+    }
+}
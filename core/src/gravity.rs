@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+/// Gravity is measured in cells per frame, frames run at 60 per second.
+pub const FRAME: Duration = Duration::from_nanos(16_666_667);
+
+/// Figure falls to the bottom within a single frame.
+pub const TWENTY_G: f32 = 20.0;
+
+/// Speed of falling figures depending on the level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GravityCurve {
+    /// Tetris guideline: `(0.8 - (level - 1) * 0.007) ^ (level - 1)` seconds per row, 20G from level 20.
+    Guideline,
+    /// Classic NES frames per row table starting from level 0.
+    Nes,
+    /// TGM internal gravity in 1/256 G steps, by section level 0 to 500.
+    /// Levels should be counted with `LevelCounting::Tgm`.
+    Tgm,
+    /// Same speed on every level.
+    Constant(f32),
+}
+
+const NES_FRAMES_PER_ROW: [u32; 30] = [
+    48, 43, 38, 33, 28, 23, 18, 13, 8, 6,
+    5, 5, 5, 4, 4, 4, 3, 3, 3, 2,
+    2, 2, 2, 2, 2, 2, 2, 2, 2, 1,
+];
+
+const TGM_GRAVITY: [(u32, u32); 30] = [
+    (0, 4), (30, 6), (35, 8), (40, 10), (50, 12), (60, 16), (70, 32), (80, 48), (90, 64), (100, 80),
+    (120, 96), (140, 112), (160, 128), (170, 144), (200, 4), (220, 32), (230, 64), (233, 96), (236, 128), (239, 160),
+    (243, 192), (247, 224), (251, 256), (300, 512), (330, 768), (360, 1024), (400, 1280), (420, 1024), (450, 768), (500, 5120),
+];
+
+impl GravityCurve {
+
+    pub fn cells_per_frame(&self, level: u32) -> f32 {
+        match *self {
+            GravityCurve::Guideline => {
+                if level >= 20 {
+                    return TWENTY_G;
+                }
+                let level = level.max(1) as f32;
+                let seconds_per_row = (0.8 - (level - 1.0) * 0.007).powf(level - 1.0);
+                (FRAME.as_secs_f32() / seconds_per_row).min(TWENTY_G)
+            },
+            GravityCurve::Nes => {
+                let i = (level as usize).min(NES_FRAMES_PER_ROW.len() - 1);
+                1.0 / NES_FRAMES_PER_ROW[i] as f32
+            },
+            GravityCurve::Tgm => {
+                let (_, gravity) = TGM_GRAVITY.iter()
+                    .take_while(|&&(from, _)| from <= level)
+                    .last()
+                    .unwrap();
+                *gravity as f32 / 256.0
+            },
+            GravityCurve::Constant(cells) => cells,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use quickcheck::quickcheck;
+
+    quickcheck! {
+
+        fn guideline_and_nes_never_slow_down(level: u32) -> bool {
+            let level = level % 40;
+            [GravityCurve::Guideline, GravityCurve::Nes].iter().all(|curve| {
+                curve.cells_per_frame(level) <= curve.cells_per_frame(level + 1)
+            })
+        }
+    }
+
+    #[test]
+    fn known_speeds() {
+        assert_eq!(GravityCurve::Nes.cells_per_frame(0), 1.0 / 48.0);
+        assert_eq!(GravityCurve::Nes.cells_per_frame(99), 1.0);
+        assert_eq!(GravityCurve::Tgm.cells_per_frame(0), 4.0 / 256.0);
+        assert_eq!(GravityCurve::Tgm.cells_per_frame(210), 4.0 / 256.0);
+        assert_eq!(GravityCurve::Tgm.cells_per_frame(999), TWENTY_G);
+        assert_eq!(GravityCurve::Guideline.cells_per_frame(20), TWENTY_G);
+        assert!((GravityCurve::Guideline.cells_per_frame(1) - 1.0 / 60.0).abs() < 0.001);
+    }
+}
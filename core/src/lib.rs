@@ -1,2 +1,16 @@
 pub mod glass;
-pub mod figures;
\ No newline at end of file
+pub mod figures;
+pub mod randomizer;
+pub mod lock_delay;
+pub mod gravity;
+pub mod scoring;
+pub mod game;
+pub mod events;
+pub mod spawn;
+pub mod error;
+pub mod pieces;
+pub mod fumen;
+#[cfg(feature = "serde")]
+pub mod encoding;
+
+pub use error::Error;
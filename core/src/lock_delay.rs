@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+/// What gives a grounded figure more time before it locks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LockReset {
+    /// the timer restarts only when the figure reaches a lower row
+    Step,
+    /// moves and rotations restart the timer too, up to `max_resets` times per row
+    Move,
+}
+
+/// Time a figure may stay on the ground before it locks.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LockDelay {
+    pub delay: Duration,
+    pub reset: LockReset,
+    pub max_resets: usize,
+    elapsed: Duration,
+    resets: usize,
+    lowest_row: Option<isize>,
+}
+
+impl LockDelay {
+    pub fn new(delay: Duration, reset: LockReset) -> LockDelay {
+        LockDelay {
+            delay,
+            reset,
+            max_resets: 15,
+            elapsed: Duration::ZERO,
+            resets: 0,
+            lowest_row: None,
+        }
+    }
+
+    /// Half a second with move reset limited to 15 moves.
+    pub fn guideline() -> LockDelay {
+        LockDelay::new(Duration::from_millis(500), LockReset::Move)
+    }
+
+    pub fn with_max_resets(mut self, max_resets: usize) -> LockDelay {
+        self.max_resets = max_resets;
+        self
+    }
+
+    /// Starts over for a newly spawned figure.
+    pub fn restart(&mut self) {
+        self.elapsed = Duration::ZERO;
+        self.resets = 0;
+        self.lowest_row = None;
+    }
+
+    /// The figure was moved sideways or rotated.
+    pub fn moved(&mut self) {
+        if self.reset == LockReset::Move && self.resets < self.max_resets {
+            self.elapsed = Duration::ZERO;
+            self.resets += 1;
+        }
+    }
+
+    /// The figure is now at `row`, reaching a new lowest row restarts the timer.
+    pub fn stepped(&mut self, row: isize) {
+        if self.lowest_row < Some(row) {
+            self.lowest_row = Some(row);
+            self.elapsed = Duration::ZERO;
+            self.resets = 0;
+        }
+    }
+
+    /// Advances the timer, returns `true` when the figure must lock.
+    pub fn update(&mut self, dt: Duration, grounded: bool) -> bool {
+        if !grounded {
+            return false;
+        }
+        if self.reset == LockReset::Move && self.resets >= self.max_resets {
+            return true;
+        }
+        self.elapsed = self.elapsed.saturating_add(dt);
+        self.elapsed >= self.delay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn locks_after_delay_on_the_ground() {
+        let mut lock = LockDelay::guideline();
+
+        assert!(!lock.update(Duration::from_secs(1), false));
+        assert!(!lock.update(FRAME * 4, true));
+        assert!(lock.update(FRAME, true));
+    }
+
+    #[test]
+    fn step_reset_ignores_moves() {
+        let mut lock = LockDelay::new(FRAME * 2, LockReset::Step);
+        lock.stepped(3);
+
+        assert!(!lock.update(FRAME, true));
+        lock.moved();
+        assert!(lock.update(FRAME, true));
+    }
+
+    #[test]
+    fn move_reset_is_capped() {
+        let mut lock = LockDelay::new(FRAME * 2, LockReset::Move).with_max_resets(3);
+        lock.stepped(3);
+
+        for _ in 0 .. 3 {
+            assert!(!lock.update(FRAME, true));
+            lock.moved();
+        }
+        assert!(lock.update(Duration::ZERO, true));
+
+        lock.stepped(4);
+        assert!(!lock.update(FRAME, true));
+    }
+}
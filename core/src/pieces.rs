@@ -0,0 +1,406 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::figures::{Blocks, Figure, FigureRepr};
+use crate::glass::Cell;
+
+/// A figure a game can deal, in its spawn orientation.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Piece {
+    pub name: String,
+    /// the tetromino this piece is, rotation systems and T-spins only know about those
+    pub figure: Option<Figure>,
+    pub shape: FigureRepr,
+    /// cell the piece leaves in the glass when it freezes
+    pub cell: Cell,
+    /// blocks in every orientation in the order of `Orientation`, `None` rotates around the centre
+    pub rotations: Option<[Blocks; 4]>,
+    /// shifts tried on every rotation, (x, y) with y pointing UP, empty uses the rotation system's
+    pub kicks: Vec<(i32, i32)>,
+    /// (columns right, rows down) from where the spawn rule puts the piece
+    pub spawn_offset: (i32, i32),
+}
+
+impl Piece {
+    pub fn new(name: &str, shape: FigureRepr, cell: Cell) -> Piece {
+        Piece {
+            name: name.to_string(),
+            figure: shape.kind(),
+            shape,
+            cell,
+            rotations: None,
+            kicks: Vec::new(),
+            spawn_offset: (0, 0),
+        }
+    }
+
+    /// Blocks of each orientation, the spawn one replaces the blocks of the shape.
+    pub fn with_rotations(mut self, rotations: [Blocks; 4]) -> Piece {
+        self.shape.blocks = rotations[0];
+        self.rotations = Some(rotations);
+        self
+    }
+
+    pub fn with_kicks(mut self, kicks: Vec<(i32, i32)>) -> Piece {
+        self.kicks = kicks;
+        self
+    }
+
+    pub fn with_spawn_offset(mut self, offset: (i32, i32)) -> Piece {
+        self.spawn_offset = offset;
+        self
+    }
+
+    pub fn from_figure(figure: Figure) -> Piece {
+        Piece::new(&figure.letter().to_string(), FigureRepr::new(figure), Cell::Piece(figure))
+    }
+}
+
+/// Pieces dealt by a game, randomizers pick them by index.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "Vec<Piece>", into = "Vec<Piece>"))]
+pub struct PieceSet {
+    pieces: Vec<Piece>,
+}
+
+/// One sided pentominoes, mirrored pieces named with a `'`.
+const PENTOMINOES: [(&str, &[&str], u32); 18] = [
+    ("F", &[".XX", "XX.", ".X."], 0xb0603c),
+    ("F'", &["XX.", ".XX", ".X."], 0x3c60b0),
+    ("I", &["XXXXX"], 0x00c8c8),
+    ("L", &["...X", "XXXX"], 0xe08000),
+    ("L'", &["X...", "XXXX"], 0x0050e0),
+    ("N", &[".XXX", "XX.."], 0x80c000),
+    ("N'", &["XXX.", "..XX"], 0xc00080),
+    ("P", &["XX", "XX", "X."], 0xe0a0c0),
+    ("P'", &["XX", "XX", ".X"], 0xa0c0e0),
+    ("T", &["XXX", ".X.", ".X."], 0x9000c0),
+    ("U", &["X.X", "XXX"], 0xe0e000),
+    ("V", &["X..", "X..", "XXX"], 0x006080),
+    ("W", &["X..", "XX.", ".XX"], 0x608000),
+    ("X", &[".X.", "XXX", ".X."], 0xc0c0c0),
+    ("Y", &["..X.", "XXXX"], 0xa05000),
+    ("Y'", &[".X..", "XXXX"], 0x0080a0),
+    ("Z", &["XX.", ".X.", ".XX"], 0xe00000),
+    ("S", &[".XX", ".X.", "XX."], 0x00b000),
+];
+
+impl PieceSet {
+    pub fn new(pieces: Vec<Piece>) -> Result<PieceSet> {
+        if pieces.is_empty() {
+            return Err(Error::EmptyPieceSet);
+        }
+        Ok(PieceSet { pieces })
+    }
+
+    /// The seven figures in the order of `Figure::ALL`.
+    pub fn tetrominoes() -> PieceSet {
+        PieceSet { pieces: Figure::ALL.iter().map(|&figure| Piece::from_figure(figure)).collect() }
+    }
+
+    /// The eighteen one sided pentominoes.
+    pub fn pentominoes() -> PieceSet {
+        let pieces = PENTOMINOES.iter().map(|&(name, rows, rgb)| {
+            let shape = FigureRepr::from_rows(rows).expect("pentominoes have five blocks");
+            Piece::new(name, shape, Cell::Custom(rgb))
+        });
+        PieceSet { pieces: pieces.collect() }
+    }
+
+    /// Reads a piece set file, see `parse` for the format.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PieceSet> {
+        let text = fs::read_to_string(path).map_err(|error| Error::Io { reason: error.to_string() })?;
+        PieceSet::parse(&text)
+    }
+
+    /// Parses pieces written as text, one piece after another:
+    ///
+    /// ```text
+    /// # lines starting with '#' are comments
+    /// piece T
+    /// color #a000f0
+    /// spawn 0 -1
+    /// kicks 0,0 -1,0 1,0
+    /// .X.
+    /// XXX
+    /// ```
+    ///
+    /// `piece` starts a piece named by the rest of the line, the settings after it are optional:
+    /// `color` is a six hex digit rgb value, pieces without one freeze as garbage; `spawn` shifts
+    /// the piece columns right and rows down from where the spawn rule puts it;
+    /// `kicks` are tried in order on every rotation, y pointing up.
+    ///
+    /// Art is `X` for blocks and `.` for empty cells. A piece has either one drawing
+    /// rotated around its centre or four separated by blank lines, in the order spawn,
+    /// right, reverse and left.
+    pub fn parse(text: &str) -> Result<PieceSet> {
+        let mut drafts: Vec<Draft> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let number = i + 1;
+            let line = line.trim();
+            let error = |reason: &str| Error::Parse { line: number, reason: reason.to_string() };
+
+            if line.starts_with('#') {
+                continue;
+            }
+            if line.is_empty() {
+                if let Some(draft) = drafts.last_mut() {
+                    if draft.arts.last().is_some_and(|art| !art.is_empty()) {
+                        draft.arts.push(Vec::new());
+                    }
+                }
+                continue;
+            }
+            if line.chars().all(|c| c == 'X' || c == '.') {
+                let draft = drafts.last_mut().ok_or_else(|| error("art before any piece"))?;
+                draft.arts.last_mut().expect("drafts start with an art").push(line);
+                continue;
+            }
+
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            if keyword == "piece" {
+                if rest.is_empty() {
+                    return Err(error("piece without a name"));
+                }
+                drafts.push(Draft::new(rest, number));
+                continue;
+            }
+            let draft = drafts.last_mut().ok_or_else(|| error("setting before any piece"))?;
+            match keyword {
+                "color" => {
+                    let hex = rest.trim_start_matches('#');
+                    let rgb = Some(hex)
+                        .filter(|hex| hex.len() == 6 && hex.bytes().all(|c| c.is_ascii_hexdigit()))
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                        .ok_or_else(|| error("color is not six hex digits of rgb"))?;
+                    draft.cell = Cell::Custom(rgb);
+                },
+                "spawn" => {
+                    let offset: Vec<i32> = rest.split_whitespace()
+                        .map(str::parse)
+                        .collect::<std::result::Result<_, _>>()
+                        .map_err(|_| error("spawn offset is not a pair of numbers"))?;
+                    match offset[..] {
+                        [x, y] => draft.spawn_offset = (x, y),
+                        _ => return Err(error("spawn offset is not a pair of numbers")),
+                    }
+                },
+                "kicks" => {
+                    draft.kicks = rest.split_whitespace()
+                        .map(|kick| {
+                            let (x, y) = kick.split_once(',')?;
+                            Some((x.parse().ok()?, y.parse().ok()?))
+                        })
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| error("kicks are not x,y pairs"))?;
+                },
+                _ => return Err(error(&format!("unknown setting `{}`", keyword))),
+            }
+        }
+
+        let pieces = drafts.into_iter().map(Draft::build).collect::<Result<_>>()?;
+        PieceSet::new(pieces)
+    }
+
+    pub fn pieces(&self) -> &[Piece] {
+        &self.pieces
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Piece> {
+        self.pieces.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pieces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    /// Whether the set holds the seven tetrominoes and nothing else, in any order.
+    pub fn is_tetrominoes(&self) -> bool {
+        self.len() == Figure::ALL.len() && Figure::ALL.iter().all(|&figure| self.find(figure).is_some())
+    }
+
+    /// Index of the piece which is `figure`.
+    pub fn find(&self, figure: Figure) -> Option<usize> {
+        self.pieces.iter().position(|piece| piece.figure == Some(figure))
+    }
+}
+
+/// Piece as read so far by `PieceSet::parse`.
+struct Draft<'a> {
+    name: &'a str,
+    /// line of the `piece` header
+    line: usize,
+    cell: Cell,
+    spawn_offset: (i32, i32),
+    kicks: Vec<(i32, i32)>,
+    /// rows of every drawing, the last one may still be empty
+    arts: Vec<Vec<&'a str>>,
+}
+
+impl<'a> Draft<'a> {
+    fn new(name: &'a str, line: usize) -> Draft<'a> {
+        Draft {
+            name,
+            line,
+            cell: Cell::Garbage,
+            spawn_offset: (0, 0),
+            kicks: Vec::new(),
+            arts: vec![Vec::new()],
+        }
+    }
+
+    fn build(mut self) -> Result<Piece> {
+        let line = self.line;
+        let error = |reason: String| Error::Parse { line, reason };
+        self.arts.retain(|art| !art.is_empty());
+
+        let shapes = self.arts.iter()
+            .map(|art| FigureRepr::from_rows(art))
+            .collect::<Result<Vec<_>>>()
+            .map_err(|e| error(format!("piece {}: {}", self.name, e)))?;
+        let piece = match shapes[..] {
+            [shape] => Piece::new(self.name, shape, self.cell),
+            [shape, right, reverse, left] => {
+                if shapes.iter().any(|s| s.blocks.len() != shape.blocks.len()) {
+                    return Err(error(format!("piece {}: orientations differ in blocks", self.name)));
+                }
+                Piece::new(self.name, shape, self.cell)
+                    .with_rotations([shape.blocks, right.blocks, reverse.blocks, left.blocks])
+            },
+            _ => return Err(error(format!("piece {}: needs one or four drawings, got {}", self.name, shapes.len()))),
+        };
+        Ok(piece.with_kicks(self.kicks).with_spawn_offset(self.spawn_offset))
+    }
+}
+
+impl TryFrom<Vec<Piece>> for PieceSet {
+    type Error = Error;
+
+    fn try_from(pieces: Vec<Piece>) -> Result<Self> {
+        PieceSet::new(pieces)
+    }
+}
+
+impl From<PieceSet> for Vec<Piece> {
+    fn from(set: PieceSet) -> Self {
+        set.pieces
+    }
+}
+
+impl Default for PieceSet {
+    fn default() -> Self {
+        PieceSet::tetrominoes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::figures::RotateDirection;
+
+    #[test]
+    fn pentominoes_are_distinct_five_block_pieces() {
+        let set = PieceSet::pentominoes();
+        assert_eq!(set.len(), 18);
+
+        let mut shapes: Vec<Vec<(i32, i32)>> = set.pieces().iter().map(|piece| {
+            let mut blocks = piece.shape.blocks.to_vec();
+            blocks.sort();
+            blocks
+        }).collect();
+        assert!(shapes.iter().all(|blocks| blocks.len() == 5));
+        shapes.sort();
+        shapes.dedup();
+        assert_eq!(shapes.len(), 18);
+    }
+
+    #[test]
+    fn pentominoes_come_back_after_four_rotations() {
+        for piece in PieceSet::pentominoes().pieces() {
+            let mut shape = piece.shape;
+            for _ in 0 .. 4 {
+                shape.rotate_to(RotateDirection::Clockwise);
+            }
+            assert_eq!(shape.blocks, piece.shape.blocks, "{}", piece.name);
+        }
+    }
+
+    const SET: &str = "\
+# a domino and a T drawn in every orientation
+piece domino
+color #ff8000
+spawn 1 -1
+XX
+
+piece T
+kicks 0,0 -1,0 1,0
+...
+XXX
+.X.
+
+.X.
+XX.
+.X.
+
+.X.
+XXX
+...
+
+.X.
+.XX
+.X.
+";
+
+    #[test]
+    fn sets_parse_from_text() {
+        let set = PieceSet::parse(SET).unwrap();
+        assert_eq!(set.len(), 2);
+
+        let domino = &set.pieces()[0];
+        assert_eq!((domino.name.as_str(), domino.cell, domino.spawn_offset), ("domino", Cell::Custom(0xff8000), (1, -1)));
+        assert_eq!(domino.shape.blocks, [(0, 0), (1, 0)]);
+        assert_eq!(domino.rotations, None);
+
+        let t = &set.pieces()[1];
+        assert_eq!((t.cell, t.figure), (Cell::Garbage, None));
+        assert_eq!(t.kicks, [(0, 0), (-1, 0), (1, 0)]);
+        let rotations = t.rotations.unwrap();
+        assert_eq!(t.shape.blocks, rotations[0]);
+        assert_eq!(rotations[1], [(1, 0), (0, 1), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_line() {
+        let error = |text: &str| match PieceSet::parse(text) {
+            Err(Error::Parse { line, .. }) => Some(line),
+            _ => None,
+        };
+        assert_eq!(error("XX"), Some(1));
+        assert_eq!(error("piece a\ncolor pink\nXX"), Some(2));
+        assert_eq!(error("piece a\ncolor #1000000\nXX"), Some(2));
+        assert_eq!(error("piece a\ncolor #fff\nXX"), Some(2));
+        assert_eq!(error("piece a\ncolor #+fffff\nXX"), Some(2));
+        assert_eq!(error("piece a\ncolor #ffffff\nXX"), None);
+        assert_eq!(error("piece a\nXX\n\nspin 1\n"), Some(4));
+        assert_eq!(error("piece a\nspawn 1\nXX"), Some(2));
+        assert_eq!(error("piece a\nXX\n\nX\nX"), Some(1));
+        assert_eq!(error("piece a\n\npiece b\nX"), Some(1));
+        assert_eq!(PieceSet::parse("# nothing").err(), Some(Error::EmptyPieceSet));
+    }
+
+    #[test]
+    fn custom_sets_need_pieces() {
+        assert_eq!(PieceSet::new(Vec::new()).err(), Some(Error::EmptyPieceSet));
+        assert_eq!(PieceSet::tetrominoes().find(Figure::Base), Some(2));
+        assert_eq!(PieceSet::pentominoes().find(Figure::Base), None);
+    }
+}
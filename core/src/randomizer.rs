@@ -0,0 +1,305 @@
+use std::collections::VecDeque;
+
+use crate::figures::Figure;
+use crate::pieces::PieceSet;
+use rand::{Rng, RngCore};
+use rand::seq::SliceRandom;
+
+/// Decides which figure comes next.
+pub trait Randomizer {
+    fn next_figure(&mut self, rng: &mut dyn RngCore) -> Figure;
+
+    /// Index of the next piece of `pieces`.
+    /// Sets of the seven tetrominoes, in any order, are dealt through `next_figure`,
+    /// other sets uniformly.
+    fn next_piece(&mut self, pieces: &PieceSet, rng: &mut dyn RngCore) -> usize {
+        if pieces.is_tetrominoes() {
+            let figure = self.next_figure(rng);
+            pieces.find(figure).unwrap()
+        } else {
+            rng.gen_range(0 .. pieces.len().max(1))
+        }
+    }
+
+    /// The randomizer with its state to restore a saved glass with, `None` if it can't be saved.
+    #[cfg(feature = "serde")]
+    fn save(&self) -> Option<SavedRandomizer> {
+        None
+    }
+}
+
+/// Randomizers a glass can be saved with, along with their state.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub enum SavedRandomizer {
+    PureRandom(PureRandom),
+    Bag(Bag),
+    History(History),
+    NesReroll(NesReroll),
+}
+
+#[cfg(feature = "serde")]
+impl SavedRandomizer {
+    pub fn restore(self) -> Box<dyn Randomizer> {
+        match self {
+            SavedRandomizer::PureRandom(randomizer) => Box::new(randomizer),
+            SavedRandomizer::Bag(randomizer) => Box::new(randomizer),
+            SavedRandomizer::History(randomizer) => Box::new(randomizer),
+            SavedRandomizer::NesReroll(randomizer) => Box::new(randomizer),
+        }
+    }
+}
+
+/// Every figure is equally likely regardless of what came before.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PureRandom;
+
+impl Randomizer for PureRandom {
+    fn next_figure(&mut self, rng: &mut dyn RngCore) -> Figure {
+        rng.gen()
+    }
+
+    #[cfg(feature = "serde")]
+    fn save(&self) -> Option<SavedRandomizer> {
+        Some(SavedRandomizer::PureRandom(self.clone()))
+    }
+}
+
+/// Deals shuffled bags holding each figure `copies` times.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bag {
+    copies: usize,
+    /// indices of the pieces left in the bag
+    bag: Vec<usize>,
+}
+
+impl Bag {
+    pub fn new(copies: usize) -> Bag {
+        Bag {
+            copies,
+            bag: Vec::with_capacity(copies * Figure::ALL.len()),
+        }
+    }
+
+    /// The guideline randomizer.
+    pub fn seven() -> Bag {
+        Bag::new(1)
+    }
+
+    pub fn fourteen() -> Bag {
+        Bag::new(2)
+    }
+}
+
+impl Randomizer for Bag {
+    fn next_figure(&mut self, rng: &mut dyn RngCore) -> Figure {
+        Figure::ALL[self.deal(Figure::ALL.len(), rng)]
+    }
+
+    fn next_piece(&mut self, pieces: &PieceSet, rng: &mut dyn RngCore) -> usize {
+        self.deal(pieces.len(), rng)
+    }
+
+    #[cfg(feature = "serde")]
+    fn save(&self) -> Option<SavedRandomizer> {
+        Some(SavedRandomizer::Bag(self.clone()))
+    }
+}
+
+impl Bag {
+    /// Index of the next of `count` pieces, refills the bag when it runs out.
+    fn deal(&mut self, count: usize, rng: &mut dyn RngCore) -> usize {
+        self.bag.retain(|&piece| piece < count);
+        if self.bag.is_empty() {
+            for _ in 0 .. self.copies.max(1) {
+                self.bag.extend(0 .. count.max(1));
+            }
+            self.bag.shuffle(rng);
+        }
+        self.bag.pop().unwrap()
+    }
+}
+
+/// TGM style randomizer: rolls up to `retries` times to avoid any of the last four figures.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct History {
+    retries: usize,
+    history: VecDeque<Figure>,
+    first: bool,
+}
+
+impl History {
+    /// The history starts as Z, Z, Z, Z, like in TGM 1.
+    pub fn new(retries: usize) -> History {
+        use crate::figures::Figure::*;
+        History {
+            retries,
+            history: VecDeque::from(vec![LeftZig; 4]),
+            first: true,
+        }
+    }
+
+    /// TGM 1 rolls four times.
+    pub fn tgm() -> History {
+        History::new(4)
+    }
+}
+
+impl Randomizer for History {
+    fn next_figure(&mut self, rng: &mut dyn RngCore) -> Figure {
+        use crate::figures::Figure::*;
+        let figure = if self.first {
+            // the very first figure is never an S, Z or O
+            self.first = false;
+            *[Line, Base, RightL, LeftL].choose(rng).unwrap()
+        } else {
+            let mut figure: Figure = rng.gen();
+            for _ in 1 .. self.retries {
+                if !self.history.contains(&figure) {
+                    break;
+                }
+                figure = rng.gen();
+            }
+            figure
+        };
+
+        self.history.pop_front();
+        self.history.push_back(figure);
+        figure
+    }
+
+    #[cfg(feature = "serde")]
+    fn save(&self) -> Option<SavedRandomizer> {
+        Some(SavedRandomizer::History(self.clone()))
+    }
+}
+
+/// Classic NES randomizer: rerolls once if the figure repeats the previous one.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NesReroll {
+    last: Option<Figure>,
+}
+
+impl NesReroll {
+    pub fn new() -> NesReroll {
+        NesReroll::default()
+    }
+}
+
+impl Randomizer for NesReroll {
+    fn next_figure(&mut self, rng: &mut dyn RngCore) -> Figure {
+        // the original rolls one of eight values, the eighth meaning "roll again"
+        let roll = rng.gen_range(0 ..= Figure::ALL.len());
+        let figure = match Figure::ALL.get(roll) {
+            Some(&figure) if Some(figure) != self.last => figure,
+            _ => rng.gen(),
+        };
+        self.last = Some(figure);
+        figure
+    }
+
+    #[cfg(feature = "serde")]
+    fn save(&self) -> Option<SavedRandomizer> {
+        Some(SavedRandomizer::NesReroll(self.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use quickcheck::quickcheck;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn deal(randomizer: &mut dyn Randomizer, seed: u64, count: usize) -> Vec<Figure> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0 .. count).map(|_| randomizer.next_figure(&mut rng)).collect()
+    }
+
+    quickcheck! {
+
+        /// every bag holds each figure the same number of times
+        fn bag_deals_every_figure(seed: u64, copies: u8) -> bool {
+            let copies = copies as usize % 3 + 1;
+            let size = copies * Figure::ALL.len();
+            let figures = deal(&mut Bag::new(copies), seed, size * 3);
+
+            figures.chunks(size).all(|bag| {
+                Figure::ALL.iter().all(|f| bag.iter().filter(|&b| b == f).count() == copies)
+            })
+        }
+
+        fn history_never_starts_with_zig_or_cube(seed: u64) -> bool {
+            use crate::figures::Figure::*;
+            let first = deal(&mut History::tgm(), seed, 1)[0];
+
+            ![Cube, LeftZig, RightZig].contains(&first)
+        }
+    }
+
+    #[test]
+    fn bag_deals_pieces_of_any_set() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut bag = Bag::seven();
+        let set = PieceSet::pentominoes();
+        let mut pieces: Vec<usize> = (0 .. 18).map(|_| bag.next_piece(&set, &mut rng)).collect();
+        pieces.sort();
+
+        assert_eq!(pieces, (0 .. 18).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reordered_tetrominoes_are_dealt_as_their_figures() {
+        let mut pieces = PieceSet::tetrominoes().pieces().to_vec();
+        pieces.reverse();
+        let set = PieceSet::new(pieces).unwrap();
+        let mut rng = StdRng::seed_from_u64(9);
+        let mut history = History::tgm();
+        let dealt: Vec<Figure> = (0 .. 50)
+            .map(|_| set.get(history.next_piece(&set, &mut rng)).unwrap().figure.unwrap())
+            .collect();
+
+        assert_eq!(dealt, deal(&mut History::tgm(), 9, 50));
+    }
+
+    #[test]
+    fn other_sets_of_seven_are_dealt_uniformly() {
+        let pieces = PieceSet::pentominoes().pieces()[.. 7].to_vec();
+        let set = PieceSet::new(pieces).unwrap();
+        let mut rng = StdRng::seed_from_u64(9);
+        // history would never deal the same figure twice in a row
+        let mut history = History::new(100);
+        let dealt: Vec<usize> = (0 .. 200).map(|_| history.next_piece(&set, &mut rng)).collect();
+
+        assert!(dealt.windows(2).any(|w| w[0] == w[1]));
+    }
+
+    #[test]
+    fn pure_random_deals_every_figure() {
+        let figures = deal(&mut PureRandom, 11, 200);
+
+        assert!(Figure::ALL.iter().all(|figure| figures.contains(figure)));
+    }
+
+    #[test]
+    fn nes_rarely_repeats_figures() {
+        let figures = deal(&mut NesReroll::new(), 11, 7000);
+        let repeats = figures.windows(2).filter(|w| w[0] == w[1]).count();
+
+        // the reroll brings repeats down from 1/7 to 1/28
+        assert!(repeats < figures.len() / 14, "{} repeats", repeats);
+        assert!(Figure::ALL.iter().all(|figure| figures.contains(figure)));
+    }
+
+    #[test]
+    fn history_avoids_recent_figures() {
+        let figures = deal(&mut History::new(100), 42, 1000);
+
+        assert!(figures.windows(2).all(|w| w[0] != w[1]));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::glass::{DropKind, Dropped, TSpin};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScoringRules {
+    /// 100/300/500/800 points times level, 1 point per soft dropped row and 2 per hard dropped row,
+    /// T-spins score 400/800/1200/1600 and minis 100/200/400,
+    /// back-to-back clears get half as much again, combos 50 points per chained clear,
+    /// all clears 800/1200/1800/2000 on top
+    Guideline,
+    /// 40/100/300/1200 points times level + 1, 1 point per soft dropped row
+    Nes,
+}
+
+/// How the level goes up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LevelCounting {
+    /// one level every `lines_per_level` cleared lines
+    #[default]
+    Lines,
+    /// TGM: one level per piece unless the level ends in 99 or is 998, one per cleared line, up to 999
+    Tgm,
+}
+
+/// Highest level of `LevelCounting::Tgm`.
+pub const TGM_MAX_LEVEL: u32 = 999;
+
+/// Keeps score, cleared lines and level.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Scoring {
+    rules: ScoringRules,
+    score: u64,
+    lines: u32,
+    start_level: u32,
+    lines_per_level: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    counting: LevelCounting,
+    /// levels gained by pieces and lines with `LevelCounting::Tgm`
+    #[cfg_attr(feature = "serde", serde(default))]
+    tgm_levels: u32,
+}
+
+impl Scoring {
+    pub fn new(rules: ScoringRules) -> Scoring {
+        let start_level = match rules {
+            ScoringRules::Guideline => 1,
+            ScoringRules::Nes => 0,
+        };
+        Scoring {
+            rules,
+            score: 0,
+            lines: 0,
+            start_level,
+            lines_per_level: 10,
+            counting: LevelCounting::Lines,
+            tgm_levels: 0,
+        }
+    }
+
+    pub fn with_start_level(mut self, level: u32) -> Scoring {
+        self.start_level = level;
+        self
+    }
+
+    pub fn with_lines_per_level(mut self, lines: u32) -> Scoring {
+        self.lines_per_level = lines.max(1);
+        self
+    }
+
+    /// TGM counting goes with `GravityCurve::Tgm`, which is keyed on its levels.
+    pub fn with_level_counting(mut self, counting: LevelCounting) -> Scoring {
+        self.counting = counting;
+        self
+    }
+
+    pub fn score(&self) -> u64 {
+        self.score
+    }
+
+    pub fn lines(&self) -> u32 {
+        self.lines
+    }
+
+    pub fn level(&self) -> u32 {
+        // saves may have any counts in them
+        match self.counting {
+            LevelCounting::Lines => self.start_level.saturating_add(self.lines / self.lines_per_level.max(1)),
+            LevelCounting::Tgm => self.start_level.saturating_add(self.tgm_levels).min(TGM_MAX_LEVEL),
+        }
+    }
+
+    /// Scores the drop, returns `true` if it brought the game to the next level,
+    /// or with TGM counting to the next section of 100 levels.
+    pub fn add(&mut self, dropped: &Dropped) -> bool {
+        let level = self.level();
+        let distance = dropped.distance as u64;
+
+        self.score = self.score.saturating_add(match (self.rules, dropped.kind) {
+            (_, DropKind::Soft) => distance,
+            (ScoringRules::Guideline, DropKind::Hard) => 2 * distance,
+            _ => 0,
+        });
+
+        if let Some(lock) = &dropped.locked {
+            let lines = lock.rows.len();
+            let (points, multiplier) = match (self.rules, lock.tspin) {
+                (ScoringRules::Guideline, Some(TSpin { mini: true, .. })) => ([100, 200, 400, 400, 400], level),
+                (ScoringRules::Guideline, Some(TSpin { mini: false, .. })) => ([400, 800, 1200, 1600, 1600], level),
+                (ScoringRules::Guideline, None) => ([0, 100, 300, 500, 800], level),
+                (ScoringRules::Nes, _) => ([0, 40, 100, 300, 1200], level + 1),
+            };
+            let mut clear = points[lines.min(4)];
+            if self.rules == ScoringRules::Guideline {
+                if lock.back_to_back > 0 {
+                    clear += clear / 2;
+                }
+                if lock.combo > 1 {
+                    clear += 50 * (lock.combo as u64 - 1);
+                }
+                if lock.all_clear {
+                    clear += [0, 800, 1200, 1800, 2000][lines.min(4)];
+                }
+            }
+            self.score = self.score.saturating_add(clear * multiplier as u64);
+            self.lines = self.lines.saturating_add(lines as u32);
+            if self.counting == LevelCounting::Tgm {
+                self.count_tgm_levels(lines as u32);
+            }
+        }
+        match self.counting {
+            LevelCounting::Lines => self.level() > level,
+            LevelCounting::Tgm => self.level() / 100 > level / 100,
+        }
+    }
+
+    /// Lines always count, the piece doesn't at the end of a section or right before the last level.
+    fn count_tgm_levels(&mut self, lines: u32) {
+        self.tgm_levels = self.tgm_levels.saturating_add(lines);
+        let level = self.level();
+        if level % 100 != 99 && level < TGM_MAX_LEVEL - 1 {
+            self.tgm_levels += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::glass::Lock;
+
+    fn locked(kind: DropKind, distance: usize, lines: usize) -> Dropped {
+        let rows = (0 .. lines).collect();
+        Dropped { kind, distance, locked: Some(Lock { rows, ..Lock::default() }) }
+    }
+
+    #[test]
+    fn guideline_scores_lines_and_drops() {
+        let mut scoring = Scoring::new(ScoringRules::Guideline);
+
+        scoring.add(&Dropped { kind: DropKind::Soft, distance: 3, locked: None });
+        scoring.add(&locked(DropKind::Hard, 10, 4));
+        scoring.add(&locked(DropKind::Gravity, 0, 1));
+
+        assert_eq!(scoring.score(), 3 + 20 + 800 + 100);
+        assert_eq!(scoring.lines(), 5);
+    }
+
+    #[test]
+    fn guideline_scores_t_spins() {
+        let mut scoring = Scoring::new(ScoringRules::Guideline).with_start_level(2);
+        let mut tsd = locked(DropKind::Gravity, 0, 2);
+        tsd.locked.as_mut().unwrap().tspin = Some(TSpin { lines: 2, mini: false });
+        let mut mini = locked(DropKind::Gravity, 0, 0);
+        mini.locked.as_mut().unwrap().tspin = Some(TSpin { lines: 0, mini: true });
+
+        scoring.add(&tsd);
+        scoring.add(&mini);
+
+        assert_eq!(scoring.score(), 1200 * 2 + 100 * 2);
+    }
+
+    #[test]
+    fn guideline_rewards_chains() {
+        let mut scoring = Scoring::new(ScoringRules::Guideline);
+        let mut tetris = locked(DropKind::Gravity, 0, 4);
+        let lock = tetris.locked.as_mut().unwrap();
+        lock.combo = 3;
+        lock.back_to_back = 1;
+        lock.all_clear = true;
+
+        scoring.add(&tetris);
+
+        assert_eq!(scoring.score(), 800 + 400 + 100 + 2000);
+    }
+
+    #[test]
+    fn nes_multiplies_by_next_level() {
+        let mut scoring = Scoring::new(ScoringRules::Nes).with_start_level(9);
+
+        scoring.add(&locked(DropKind::Hard, 10, 4));
+
+        assert_eq!(scoring.score(), 1200 * 10);
+    }
+
+    #[test]
+    fn tgm_counts_pieces_and_lines() {
+        let mut scoring = Scoring::new(ScoringRules::Guideline).with_start_level(0).with_level_counting(LevelCounting::Tgm);
+
+        assert!(!scoring.add(&locked(DropKind::Gravity, 0, 0)));
+        assert!(!scoring.add(&locked(DropKind::Hard, 5, 2)));
+        assert!(!scoring.add(&Dropped { kind: DropKind::Soft, distance: 1, locked: None }));
+        assert_eq!(scoring.level(), 4);
+
+        // pieces stop at the end of the section, only lines get past it
+        for _ in 0 .. 200 {
+            assert!(!scoring.add(&locked(DropKind::Gravity, 0, 0)));
+        }
+        assert_eq!(scoring.level(), 99);
+        assert!(scoring.add(&locked(DropKind::Gravity, 0, 1)));
+        assert_eq!(scoring.level(), 101);
+    }
+
+    #[test]
+    fn tgm_stops_before_the_last_level() {
+        let mut scoring = Scoring::new(ScoringRules::Guideline).with_start_level(990).with_level_counting(LevelCounting::Tgm);
+
+        for _ in 0 .. 20 {
+            scoring.add(&locked(DropKind::Gravity, 0, 0));
+        }
+        assert_eq!(scoring.level(), 998);
+        scoring.add(&locked(DropKind::Gravity, 0, 4));
+        assert_eq!(scoring.level(), TGM_MAX_LEVEL);
+    }
+
+    #[test]
+    fn level_goes_up_every_n_lines() {
+        let mut scoring = Scoring::new(ScoringRules::Guideline).with_lines_per_level(5);
+
+        assert!(!scoring.add(&locked(DropKind::Gravity, 0, 4)));
+        assert!(scoring.add(&locked(DropKind::Gravity, 0, 2)));
+        assert_eq!(scoring.level(), 2);
+    }
+}
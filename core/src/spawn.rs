@@ -0,0 +1,76 @@
+use crate::figures::{Figure, Orientation};
+
+/// Conventions deciding how and where new figures enter the glass.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SpawnRule {
+    /// flat side down, centred rounding to the left, right above the visible rows and dropping a row into them
+    Guideline,
+    /// T, L and J flat side up, centred rounding to the left, in the top visible rows
+    Tgm,
+    /// T, L and J flat side up, centred rounding to the right, in the top visible rows
+    Nes,
+}
+
+impl SpawnRule {
+    /// Orientation the figure spawns in, orientations are named after the SRS shapes.
+    pub fn orientation(self, figure: Figure) -> Orientation {
+        match (self, figure) {
+            (SpawnRule::Guideline, _) => Orientation::Spawn,
+            (_, Figure::Base) | (_, Figure::RightL) | (_, Figure::LeftL) => Orientation::Reverse,
+            _ => Orientation::Spawn,
+        }
+    }
+
+    /// Leftmost column taken by the spawned figure in a glass `width` cells wide.
+    pub fn column(self, figure: Figure, width: usize) -> usize {
+        let figure_width = match figure {
+            Figure::Cube => 2,
+            Figure::Line => 4,
+            _ => 3,
+        };
+        self.column_for_width(figure_width, width)
+    }
+
+    /// Leftmost column taken by a spawned figure `figure_width` cells wide.
+    pub fn column_for_width(self, figure_width: usize, width: usize) -> usize {
+        let free = width.saturating_sub(figure_width);
+        match self {
+            SpawnRule::Nes => free.div_ceil(2),
+            _ => free / 2,
+        }
+    }
+
+    /// Figures spawn in the hidden buffer rather than in the visible rows.
+    pub fn in_buffer(self) -> bool {
+        self == SpawnRule::Guideline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guideline_columns_on_standard_width() {
+        let columns: Vec<usize> = Figure::ALL.iter().map(|&f| SpawnRule::Guideline.column(f, 10)).collect();
+
+        // O in the middle columns, I in the middle four, the rest rounding to the left
+        assert_eq!(columns, [4, 3, 3, 3, 3, 3, 3]);
+    }
+
+    #[test]
+    fn nes_rounds_to_the_right() {
+        assert_eq!(SpawnRule::Nes.column(Figure::Base, 10), 4);
+        assert_eq!(SpawnRule::Nes.column(Figure::Line, 10), 3);
+        assert_eq!(SpawnRule::Nes.column(Figure::Cube, 10), 4);
+        assert_eq!(SpawnRule::Nes.column(Figure::Cube, 3), 1);
+    }
+
+    #[test]
+    fn tgm_flips_t_l_and_j() {
+        assert_eq!(SpawnRule::Tgm.orientation(Figure::LeftL), Orientation::Reverse);
+        assert_eq!(SpawnRule::Tgm.orientation(Figure::LeftZig), Orientation::Spawn);
+        assert_eq!(SpawnRule::Guideline.orientation(Figure::Base), Orientation::Spawn);
+    }
+}
@@ -3,46 +3,85 @@ extern crate ggez;
 //extern crate rand;
 use ggez::conf;
 use ggez::event::*;
-use ggez::{Context, ContextBuilder, GameResult};
+use ggez::{Context, ContextBuilder, GameError, GameResult};
 use ggez::graphics;
 use ggez::graphics::{DrawMode, Rect};
 use ggez::timer;
+use std::time::Duration;
 //use ggez::nalgebra as na;
 
-use glass::Glass;
+use core::events::Event;
+use core::figures::{Figure, RotateDirection};
+use core::game::{Game, GameState};
+use core::glass::{Cell, Glass, MoveDirection, TSpin};
+use core::pieces::PieceSet;
 
-//use figures::Figure;
-use glass::MoveDirection;
-
-use core::glass::{Glass, MoveDirection};
+fn cell_color(cell: Cell, alpha: u8) -> graphics::Color {
+    let rgb = match cell {
+        Cell::Piece(Figure::Cube) => 0xf0f000,
+        Cell::Piece(Figure::Line) => 0x00f0f0,
+        Cell::Piece(Figure::Base) => 0xa000f0,
+        Cell::Piece(Figure::LeftZig) => 0xf00000,
+        Cell::Piece(Figure::RightZig) => 0x00f000,
+        Cell::Piece(Figure::RightL) => 0xf0a000,
+        Cell::Piece(Figure::LeftL) => 0x0000f0,
+        Cell::Empty | Cell::Garbage => 0x9a9a9a,
+        Cell::Custom(rgb) => rgb,
+    };
+    ((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, alpha).into()
+}
 
 struct MainState {
     screen_width: u32,
     screen_height: u32,
-    glass: Glass,
-    block_size: f32
+    game: Game,
+    block_size: f32,
+    /// last notable thing that happened, like a T-spin or a level up
+    announcement: String,
 }
 
 impl MainState {
     fn new(ctx: &mut Context) -> GameResult<MainState> {
         ctx.print_resource_stats();
         graphics::set_background_color(ctx, (33, 55, 122, 255).into());
-        let mut glass = Glass::new(12, 26);
-        glass.next_figure();
+        let mut glass = Glass::new(12, 26).with_buffer(20).with_preview(3);
+        // a piece set file given on the command line replaces the tetrominoes
+        if let Some(path) = std::env::args().nth(1) {
+            let pieces = PieceSet::load(path).map_err(|error| GameError::UnknownError(error.to_string()))?;
+            glass = glass.with_piece_set(pieces);
+        }
+        let mut game = Game::new(glass)
+            .with_countdown(Duration::from_secs(3));
+        game.start().map_err(|error| GameError::UnknownError(error.to_string()))?;
 
         let screen_width = ctx.conf.window_mode.width;
         let screen_height = ctx.conf.window_mode.height;
-        let block_size = screen_height as f32 * 3.0/4.0 / glass.height as f32;
+        let block_size = screen_height as f32 * 3.0/4.0 / game.glass.height as f32;
         let main_state = MainState {
             screen_width,
             screen_height,
-            glass,
+            game,
             block_size,
+            announcement: String::new(),
         };
 
         Ok(main_state)
     }
 
+    fn handle_events(&mut self) {
+        for event in self.game.drain_events() {
+            self.announcement = match event {
+                Event::TSpin(TSpin { lines, mini }) => {
+                    let mini = if mini { "Mini " } else { "" };
+                    format!("{}T-Spin {}", mini, ["", "Single", "Double", "Triple"][lines.min(3)])
+                },
+                Event::LinesCleared { ref rows } if rows.len() >= 4 => "Tetris".to_string(),
+                Event::LevelUp { level } => format!("Level {}", level),
+                _ => continue,
+            };
+        }
+    }
+
     fn draw_glass(&self, ctx: &mut Context) -> GameResult<()> {
         let w = self.glass_width();
         let x = self.glass_x();
@@ -53,7 +92,7 @@ impl MainState {
     }
 
     fn glass_width(&self) -> f32 {
-        self.block_size * self.glass.width as f32
+        self.block_size * self.game.glass.width as f32
     }
 
     fn glass_x(&self) -> f32 {
@@ -62,7 +101,7 @@ impl MainState {
     }
 
     fn glass_height(&self) -> f32 {
-        self.block_size * self.glass.height as f32
+        self.block_size * self.game.glass.height as f32
     }
 
     fn glass_y(&self) -> f32 {
@@ -70,15 +109,33 @@ impl MainState {
         (self.screen_height as f32 - h) / 2.0
     }
 
+    /// Screen y of a glass row, `None` for the hidden buffer rows.
+    fn row_y(&self, row: i32) -> Option<f32> {
+        let visible_row = row - self.game.glass.buffer() as i32;
+        if visible_row < 0 {
+            None
+        } else {
+            Some(self.glass_y() + visible_row as f32 * self.block_size)
+        }
+    }
+
     fn draw_figure(&self, ctx: &mut Context) -> GameResult<()> {
-        if self.glass.figure.is_some() {
-            let figure = self.glass.figure.unwrap(); //TODO: FIX!
+        if self.game.glass.figure.is_some() {
+            let figure = self.game.glass.figure.unwrap(); //TODO: FIX!
+            let cell = match figure.piece.and_then(|piece| self.game.glass.piece_set().get(piece)) {
+                Some(piece) => piece.cell,
+                None => figure.figure.kind().map_or(Cell::Garbage, Cell::Piece),
+            };
+            graphics::set_color(ctx, cell_color(cell, 64))?;
 
             for &(col, row) in figure.figure.blocks.iter() {
                 let w = self.block_size;
                 let (f_row, f_col) = figure.position;
                 let x = self.glass_x() + (f_col as f32 + col as f32) * w;
-                let y = self.glass_y() + (f_row as f32 + row as f32) * w;
+                let y = match self.row_y(f_row as i32 + row) {
+                    Some(y) => y,
+                    None => continue,
+                };
 
                 graphics::rectangle(ctx, DrawMode::Fill, Rect { x, y, w, h: w })?;
                 graphics::rectangle(ctx, DrawMode::Line(1.0), Rect { x, y, w, h: w })?;
@@ -87,15 +144,77 @@ impl MainState {
         Ok(())
     }
 
+    fn draw_ghost(&self, ctx: &mut Context) -> GameResult<()> {
+        if let Some(blocks) = self.game.glass.ghost_coordinates() {
+            let w = self.block_size;
+            for &(col, row) in blocks.iter() {
+                let x = self.glass_x() + col as f32 * w;
+                if let Some(y) = self.row_y(row) {
+                    graphics::rectangle(ctx, DrawMode::Line(1.0), Rect { x, y, w, h: w })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_preview(&self, ctx: &mut Context) -> GameResult<()> {
+        let w = self.block_size;
+        let x0 = self.glass_x() + self.glass_width() + w;
+        let y0 = self.glass_y();
+        for (i, piece) in self.game.glass.preview().enumerate() {
+            graphics::set_color(ctx, cell_color(piece.cell, 255))?;
+            for &(col, row) in piece.shape.blocks.iter() {
+                let x = x0 + col as f32 * w;
+                let y = y0 + (i as f32 * 4.0 + row as f32) * w;
+                graphics::rectangle(ctx, DrawMode::Fill, Rect { x, y, w, h: w })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_hold(&self, ctx: &mut Context) -> GameResult<()> {
+        if let Some(piece) = self.game.glass.held() {
+            let w = self.block_size;
+            let x0 = self.glass_x() - 6.0 * w;
+            let y0 = self.glass_y();
+            graphics::set_color(ctx, cell_color(piece.cell, 255))?;
+            for &(col, row) in piece.shape.blocks.iter() {
+                let x = x0 + col as f32 * w;
+                let y = y0 + row as f32 * w;
+                graphics::rectangle(ctx, DrawMode::Fill, Rect { x, y, w, h: w })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_score(&self, ctx: &mut Context) -> GameResult<()> {
+        let scoring = self.game.scoring();
+        let state = match self.game.state() {
+            GameState::Countdown { remaining } => format!("{}", remaining.as_secs() + 1),
+            GameState::Paused => "Paused".to_string(),
+            GameState::GameOver { reason } => format!("Game Over: {:?}", reason),
+            _ => String::new(),
+        };
+        let line = format!("Score: {}  Level: {}  Lines: {}  {}  {}", scoring.score(), scoring.level(), scoring.lines(), state, self.announcement);
+        let font = graphics::Font::default_font()?;
+        let text = graphics::Text::new(ctx, &line, &font)?;
+        let x = self.glass_x();
+        let y = self.glass_y() - 2.0 * self.block_size;
+        graphics::draw(ctx, &text, graphics::Point2::new(x, y), 0.0)?;
+        Ok(())
+    }
+
     fn draw_content(&self, ctx: &mut Context) -> GameResult<()> {
         let w = self.block_size;
         let x0 = self.glass_x();
         let y0 = self.glass_y();
-        for row in 0 .. self.glass.height {
-            for col in 0 .. self.glass.width {
-                if self.glass[row][col] {
+        for (visible_row, row) in self.game.glass.visible_rows().enumerate() {
+            for col in 0 .. self.game.glass.width {
+                let cell = self.game.glass[row][col];
+                if cell.is_filled() {
+                    graphics::set_color(ctx, cell_color(cell, 255))?;
                     let x = x0 + col as f32 * w;
-                    let y = y0 + row as f32 * w;
+                    let y = y0 + visible_row as f32 * w;
                     graphics::rectangle(ctx, DrawMode::Fill, Rect { x, y, w, h: w })?;
                 }
             }
@@ -106,26 +225,27 @@ impl MainState {
 
 impl EventHandler for MainState {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        self.glass.clean_filled_rows();
-        while timer::check_update_time(ctx, 1) {
-            if !self.glass.relocate_figure(MoveDirection::Down) {
-                self.glass.freeze_figure();
-                self.glass.next_figure();
-            }
-        }
+        self.game.update(timer::get_delta(ctx));
+        self.handle_events();
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
         graphics::clear(ctx);
 
-        graphics::set_color(ctx, (133, 123, 55, 255).into())?;
         self.draw_content(ctx)?;
+        self.draw_preview(ctx)?;
+        self.draw_hold(ctx)?;
+
+        graphics::set_color(ctx, (133, 123, 55, 255).into())?;
+        self.draw_score(ctx)?;
 
         graphics::set_color(ctx, (135, 55, 5, 255).into())?;
         self.draw_glass(ctx)?;
 
-        graphics::set_color(ctx, (133, 123, 55, 64).into())?;
+        graphics::set_color(ctx, (133, 123, 55, 96).into())?;
+        self.draw_ghost(ctx)?;
+
         self.draw_figure(ctx)?;
 
         graphics::present(ctx);
@@ -139,19 +259,30 @@ impl EventHandler for MainState {
         }
         match keycode {
             Keycode::Right => {
-                self.glass.relocate_figure(MoveDirection::Right);
+                self.game.move_figure(MoveDirection::Right);
             },
             Keycode::Left => {
-                self.glass.relocate_figure(MoveDirection::Left);
+                self.game.move_figure(MoveDirection::Left);
             },
             Keycode::Up => {
-                self.glass.rotate_figure();
+                self.game.rotate_figure(RotateDirection::Clockwise);
             },
-            Keycode::Down => {
-                while self.glass.relocate_figure(MoveDirection::Down) {
+            Keycode::Z => {
+                self.game.rotate_figure(RotateDirection::CounterClockwise);
+            },
+            Keycode::C => {
+                self.game.hold_figure();
+            },
+            Keycode::P => {
+                if self.game.pause().is_err() {
+                    self.game.resume().ok();
                 }
-//                self.glass.freeze_figure();
-//                self.glass.next_figure();
+            },
+            Keycode::Down => {
+                self.game.soft_drop();
+            },
+            Keycode::Space => {
+                self.game.hard_drop();
             },
             _ => {}
         }
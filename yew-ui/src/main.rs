@@ -1,6 +1,14 @@
+use std::time::Duration;
 use gloo::timers::callback::Interval;
 use yew::{classes, html, Component, Context, Html, KeyboardEvent};
-use core::glass::{Glass, MoveDirection};
+use core::events::Event;
+use core::figures::{Blocks, Figure, RotateDirection};
+use core::game::{Game, GameState};
+use core::glass::{Cell, DropKind, Glass, MoveDirection, TSpin};
+use core::pieces::Piece;
+
+/// Ticks come about once a frame, the game is advanced by the time that actually passed.
+const FRAME_MILLIS: u32 = 16;
 
 enum Msg {
     Start,
@@ -8,81 +16,122 @@ enum Msg {
     Resume,
     Left,
     Rotate,
+    RotateBack,
     Right,
     Drop,
+    HardDrop,
+    Hold,
     Tick,
 }
 
 struct App {
-    glass: Glass,
+    game: Game,
     game_ticks: Option<Interval>,
+    /// `Date::now` of the last tick, in milliseconds
+    last_tick: f64,
+    /// last notable thing that happened, like a T-spin or a level up
+    announcement: String,
 }
 
 impl App {
-    fn make_progress(&mut self) {
-        if !self.glass.relocate_figure(MoveDirection::Down) {
-            self.glass.freeze_figure();
-            if self.glass.next_figure() {
-                //TODO game over?
-                self.stop_game_ticks();
-            }
+    fn make_progress(&mut self, kind: DropKind) {
+        self.game.drop_figure(kind);
+        self.stop_if_game_over();
+    }
+
+    fn advance_time(&mut self) {
+        let now = js_sys::Date::now();
+        // the clock may be set back
+        let elapsed = (now - self.last_tick).max(0.0);
+        self.last_tick = now;
+        self.game.update(Duration::from_secs_f64(elapsed / 1000.0));
+        self.stop_if_game_over();
+    }
+
+    fn stop_if_game_over(&mut self) {
+        if let GameState::GameOver { .. } = self.game.state() {
+            self.stop_game_ticks();
         }
-        self.glass.clean_filled_rows();
     }
 
     fn start_new_game(&mut self, ctx: &Context<Self>) {
-        self.glass = Self::new_glass();
-        self.glass.next_figure();
+        self.game = Self::new_game();
+        self.announcement.clear();
+        self.game.start().ok();
         self.start_game_ticks(ctx);
     }
 
     fn move_left(&mut self) {
-        self.glass.relocate_figure(MoveDirection::Left);
+        self.game.move_figure(MoveDirection::Left);
     }
 
-    fn rotate(&mut self) {
-        self.glass.rotate_figure();
+    fn rotate(&mut self, direction: RotateDirection) {
+        self.game.rotate_figure(direction);
     }
 
     fn move_right(&mut self) {
-        self.glass.relocate_figure(MoveDirection::Right);
+        self.game.move_figure(MoveDirection::Right);
+    }
+
+    fn hold(&mut self) {
+        self.game.hold_figure();
+    }
+
+    fn handle_events(&mut self) {
+        for event in self.game.drain_events() {
+            self.announcement = match event {
+                Event::TSpin(TSpin { lines, mini }) => {
+                    let mini = if mini { "Mini " } else { "" };
+                    format!("{}T-Spin {}", mini, ["", "Single", "Double", "Triple"][lines.min(3)])
+                },
+                Event::LinesCleared { ref rows } if rows.len() >= 4 => "Tetris".to_string(),
+                Event::LevelUp { level } => format!("Level {}", level),
+                _ => continue,
+            };
+        }
     }
 }
 
 impl App {
     fn render_rows(&self) -> Vec<Html> {
-        let figure_coordinates = self.glass.figure_coordinates();
+        let figure_coordinates = self.game.glass.figure_coordinates();
+        let ghost_coordinates = self.game.glass.ghost_coordinates();
 
         //TODO avoid heap allocation
-        let mut rows = Vec::with_capacity(self.glass.height);
-        for y in 0..self.glass.height {
-            rows.push(self.render_row(y, figure_coordinates));
+        let mut rows = Vec::with_capacity(self.game.glass.height);
+        for y in self.game.glass.visible_rows() {
+            rows.push(self.render_row(y, &figure_coordinates, &ghost_coordinates));
         }
         rows
     }
 
-    fn render_row(&self, y: usize, figure_coordinates: Option<[(i32, i32); 4]>) -> Html {
-        let row = &self.glass[y];
-
-        let cells: Vec<_> = row.iter().enumerate().map(|(x, v)| {
-            let mut color = *v;
-            if !color {
-                //TODO make this check part of the Glass interface
-                if let Some(points) = figure_coordinates {
-                    color = points.iter().find(|(px, py)| x == *px as usize && y == *py as usize).is_some();
-                }
+    fn render_row(&self, y: usize, figure_coordinates: &Option<Blocks>, ghost_coordinates: &Option<Blocks>) -> Html {
+        let glass = &self.game.glass;
+        let row = &glass[y];
+        let figure_cell = glass.figure.map(|f| {
+            match f.piece.and_then(|piece| glass.piece_set().get(piece)) {
+                Some(piece) => piece.cell,
+                None => f.figure.kind().map_or(Cell::Garbage, Cell::Piece),
             }
+        });
 
-            let cellule_status = {
-                if color {
-                    "cellule-live"
-                } else {
-                    "cellule-dead"
-                }
+        //TODO make this check part of the Glass interface
+        let covers = |coordinates: &Option<Blocks>, x: usize| {
+            coordinates.iter().flatten().any(|(px, py)| x == *px as usize && y == *py as usize)
+        };
+
+        let cells: Vec<_> = row.iter().enumerate().map(|(x, &cell)| {
+            let cell = match figure_cell {
+                Some(figure_cell) if covers(figure_coordinates, x) => figure_cell,
+                _ => cell,
             };
-            let idx = y * self.glass.width + x;
+            let (cellule_status, style) = match cell {
+                Cell::Empty if covers(ghost_coordinates, x) => ("cellule-ghost", None),
+                cell => Self::cell_class(cell),
+            };
+            let idx = y * self.game.glass.width + x;
             html! {
-                <div key={idx} class={classes!("game-cellule", cellule_status)}>
+                <div key={idx} class={classes!("game-cellule", cellule_status)} {style}>
                 </div>
             }
         }).collect();
@@ -94,13 +143,114 @@ impl App {
         }
     }
 
-    fn new_glass() -> Glass {
-        Glass::new(12, 26)
+    fn figure_class(figure: Figure) -> &'static str {
+        match figure {
+            Figure::Cube => "cellule-cube",
+            Figure::Line => "cellule-line",
+            Figure::Base => "cellule-base",
+            Figure::LeftZig => "cellule-left-zig",
+            Figure::RightZig => "cellule-right-zig",
+            Figure::RightL => "cellule-right-l",
+            Figure::LeftL => "cellule-left-l",
+        }
+    }
+
+    fn cell_class(cell: Cell) -> (&'static str, Option<String>) {
+        match cell {
+            Cell::Empty => ("cellule-dead", None),
+            Cell::Piece(figure) => (Self::figure_class(figure), None),
+            Cell::Garbage => ("cellule-garbage", None),
+            Cell::Custom(rgb) => ("cellule-live", Some(format!("background-color: #{:06x}", rgb))),
+        }
+    }
+
+    /// Renders the piece in a box of at least 2x4 cells.
+    fn render_figure(key: usize, piece: Option<&Piece>) -> Html {
+        let (top, bottom, left, right) = match piece {
+            Some(piece) => {
+                let shape = &piece.shape;
+                let (top, left) = (shape.min_y() as i32, shape.min_x() as i32);
+                (top, shape.max_y().max(shape.min_y() + 1) as i32, left, left + shape.width().max(4) as i32)
+            },
+            None => (0, 1, 0, 4),
+        };
+        let rows = (top ..= bottom).map(|y| {
+            let cells = (left .. right).map(|x| {
+                let (cellule_status, style) = match piece {
+                    Some(piece) if piece.shape.blocks.contains(&(x, y)) => Self::cell_class(piece.cell),
+                    _ => ("cellule-dead", None),
+                };
+                html! {
+                    <div key={x} class={classes!("game-cellule", cellule_status)} {style}>
+                    </div>
+                }
+            });
+            html! {
+                <div key={y} class="game-row">
+                    { for cells }
+                </div>
+            }
+        });
+        html! {
+            <div key={key} class="game-preview-figure">
+                { for rows }
+            </div>
+        }
+    }
+
+    fn render_preview(&self) -> Html {
+        let figures = self.game.glass.preview().enumerate().map(|(i, piece)| {
+            Self::render_figure(i, Some(piece))
+        });
+
+        html! {
+            <div class="game-preview">
+                { for figures }
+            </div>
+        }
+    }
+
+    fn render_state(&self) -> Html {
+        let state = match self.game.state() {
+            GameState::Ready => "Press Start".to_string(),
+            GameState::Countdown { remaining } => format!("{}", remaining.as_secs() + 1),
+            GameState::Playing | GameState::LineClearAnimation { .. } => self.announcement.clone(),
+            GameState::Paused => "Paused".to_string(),
+            GameState::GameOver { reason } => format!("Game Over: {:?}", reason),
+        };
+        html! {
+            <section class="game-state">{ state }</section>
+        }
+    }
+
+    fn render_score(&self) -> Html {
+        let scoring = self.game.scoring();
+        html! {
+            <section class="game-score">
+                <span>{ format!("Score: {}", scoring.score()) }</span>
+                <span>{ format!("Level: {}", scoring.level()) }</span>
+                <span>{ format!("Lines: {}", scoring.lines()) }</span>
+            </section>
+        }
+    }
+
+    fn render_hold(&self) -> Html {
+        html! {
+            <div class="game-hold">
+                { Self::render_figure(0, self.game.glass.held()) }
+            </div>
+        }
+    }
+
+    fn new_game() -> Game {
+        Game::new(Glass::new(12, 26).with_buffer(20).with_preview(3))
+            .with_countdown(Duration::from_secs(3))
     }
 
     fn start_game_ticks(&mut self, ctx: &Context<Self>) {
         let callback = ctx.link().callback(|_| Msg::Tick);
-        self.game_ticks.replace(Interval::new(670, move || callback.emit(())));
+        self.last_tick = js_sys::Date::now();
+        self.game_ticks.replace(Interval::new(FRAME_MILLIS, move || callback.emit(())));
     }
 
     fn stop_game_ticks(&mut self) {
@@ -109,8 +259,10 @@ impl App {
 
     fn new() -> App {
         Self {
-            glass: Self::new_glass(),
+            game: Self::new_game(),
             game_ticks: None,
+            last_tick: 0.0,
+            announcement: String::new(),
         }
     }
 }
@@ -129,27 +281,37 @@ impl Component for App {
                 self.start_new_game(ctx);
             },
             Msg::Pause => {
-                self.stop_game_ticks();
+                self.game.pause().ok();
             },
             Msg::Resume => {
-                self.start_game_ticks(ctx);
+                self.game.resume().ok();
             },
             Msg::Tick => {
-                self.make_progress();
+                self.advance_time();
             },
             Msg::Drop => {
-                self.make_progress();
+                self.make_progress(DropKind::Soft);
+            },
+            Msg::HardDrop => {
+                self.make_progress(DropKind::Hard);
+            },
+            Msg::Hold => {
+                self.hold();
             },
             Msg::Left => {
                 self.move_left();
             },
             Msg::Rotate => {
-                self.rotate();
+                self.rotate(RotateDirection::Clockwise);
+            },
+            Msg::RotateBack => {
+                self.rotate(RotateDirection::CounterClockwise);
             },
             Msg::Right => {
                 self.move_right();
             },
         }
+        self.handle_events();
         true
     }
 
@@ -158,10 +320,13 @@ impl Component for App {
 
         let onkeydown = ctx.link().batch_callback(|event: KeyboardEvent| {
             match event.key_code() {
+                32 => Some(Msg::HardDrop),
                 37 => Some(Msg::Left),
                 38 => Some(Msg::Rotate),
                 39 => Some(Msg::Right),
                 40 => Some(Msg::Drop),
+                67 => Some(Msg::Hold),
+                90 => Some(Msg::RotateBack),
                 _ => None,
             }
         });
@@ -173,11 +338,15 @@ impl Component for App {
                     <button onclick={ctx.link().callback(|_| Msg::Pause)}>{ "Pause" }</button>
                     <button onclick={ctx.link().callback(|_| Msg::Resume)}>{ "Resume" }</button>
                 </section>
+                { self.render_score() }
+                { self.render_state() }
                 <section class="game-container">
                     <section class="game-area">
+                        { self.render_hold() }
                         <div class="game-of-life">
                             { for cell_rows }
                         </div>
+                        { self.render_preview() }
                     </section>
                 </section>
                 <section>
@@ -185,6 +354,8 @@ impl Component for App {
                     <button onclick={ctx.link().callback(|_| Msg::Rotate)}>{ "Rotate" }</button>
                     <button onclick={ctx.link().callback(|_| Msg::Right)}>{ "Right" }</button>
                     <button onclick={ctx.link().callback(|_| Msg::Drop)}>{ "Drop" }</button>
+                    <button onclick={ctx.link().callback(|_| Msg::HardDrop)}>{ "Hard Drop" }</button>
+                    <button onclick={ctx.link().callback(|_| Msg::Hold)}>{ "Hold" }</button>
                 </section>
             </div>
         }